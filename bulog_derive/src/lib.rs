@@ -1,8 +1,11 @@
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
-use syn::{Data, DeriveInput, Fields};
+use syn::{Data, DeriveInput, Fields, Meta, punctuated::Punctuated, token::Comma};
 
-#[proc_macro_derive(Optional)]
+/// 生成所有字段都是`Option`的`{Name}Option`结构
+///
+/// `#[optional(...)]`中的内容会作为属性添加到生成的结构上, 例如`#[optional(derive(...))]`
+#[proc_macro_derive(Optional, attributes(optional))]
 pub fn optional(input: TokenStream) -> TokenStream {
     let ast = syn::parse_macro_input!(input as DeriveInput);
     let name = format_ident!("{}Option", &ast.ident);
@@ -15,19 +18,33 @@ pub fn optional(input: TokenStream) -> TokenStream {
         _ => panic!("MakeOptional can only be used with structs."),
     };
 
+    let mut attrs = Vec::new();
+    for attr in ast.attrs.iter().filter(|attr| attr.path().is_ident("optional")) {
+        match attr.parse_args_with(Punctuated::<Meta, Comma>::parse_terminated) {
+            Ok(metas) => attrs.extend(metas),
+            Err(err) => return err.to_compile_error().into(),
+        }
+    }
+
     let optional_fields: Vec<_> = fields
         .iter()
         .map(|f| {
+            let docs = f.attrs.iter().filter(|attr| attr.path().is_ident("doc"));
             let field_name = &f.ident;
             let field_type = &f.ty;
-            quote! { #field_name: Option<#field_type> }
+            quote! {
+                #(#docs)*
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub #field_name: Option<#field_type>
+            }
         })
         .collect();
 
     (quote! {
         #[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+        #(#[#attrs])*
         pub struct #name {
-            #(#[serde(skip_serializing_if = "Option::is_none")] pub #optional_fields),*
+            #(#optional_fields),*
         }
     })
     .into()
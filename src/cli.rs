@@ -0,0 +1,641 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use surrealdb::{Surreal, engine::any::Any};
+
+use crate::{
+    config::ServerConfig,
+    db::{
+        archive::{Conflict, ImportReport as ArchiveReport, export_archive, import_archive},
+        backup::{create_backup, resolve_backup, restore_backup},
+        model::{
+            config::{ConfigRecord, SETTINGS_VERSION},
+            post::NewPost,
+        },
+        repo::{self, CachedRepo, Repository, require_surreal},
+        schema::SCHEMA_VERSION,
+    },
+    importer::{
+        ImportReport, Outcome,
+        markdown::{import_markdown, split_title},
+        wordpress::{WordpressOptions, import_wordpress},
+    },
+    site::export_site,
+    storage::{self, DynStorage},
+};
+
+/// bulog博客服务器以及管理命令
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// 配置文件路径, 默认读取`./bulog.toml`
+    #[arg(short, long, env = "BU_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+    /// 覆盖`server.bind`
+    #[arg(long, global = true)]
+    pub bind: Option<String>,
+    /// 覆盖`database.endpoint`
+    #[arg(long, global = true)]
+    pub endpoint: Option<String>,
+    /// 覆盖`log.filter`
+    #[arg(long, global = true)]
+    pub log: Option<String>,
+    /// 打印合并后的配置并退出
+    #[arg(long, global = true)]
+    pub print_config: bool,
+    /// 没有子命令时启动服务器
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// 除了`serve`以外的子命令执行完就退出, rocksdb和surrealkv不允许多个进程同时打开,
+// 使用这些后端时需要先停止正在运行的服务器
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// 启动服务器
+    Serve,
+    /// 不经过网页直接安装博客
+    Install {
+        #[arg(long)]
+        title: String,
+        #[arg(long, default_value = "")]
+        description: String,
+        /// 管理员密码, 避免出现在shell历史中可以改用环境变量
+        #[arg(long, env = "BU_ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+    /// 重置管理员密码并注销所有已登录的会话
+    ResetPassword {
+        #[arg(long, env = "BU_ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+    /// 把所有表的记录和上传的文件导出到一个目录, 可以导入到使用其他存储引擎的实例
+    Export { output: PathBuf },
+    /// 导入`export`导出的目录
+    Import {
+        input: PathBuf,
+        /// 记录id已经存在时的处理方式
+        #[arg(long, value_enum, default_value_t = Conflict::Skip)]
+        on_conflict: Conflict,
+        /// `--on-conflict overwrite`时同时替换站点设置, 密码和会话密钥
+        #[arg(long)]
+        overwrite_settings: bool,
+    },
+    /// 在`backup.dir`中创建一份备份, 服务器运行时可以改用`POST /v1/backup`
+    Backup,
+    /// 清空数据库后从备份恢复, 需要先停止服务器
+    Restore {
+        /// 备份目录的路径或者`backup.dir`中的备份名称, 默认使用最新的备份
+        backup: Option<PathBuf>,
+    },
+    /// 升级数据库中的数据后退出, 服务器启动时也会自动执行
+    Migrate,
+    /// 从Markdown文件创建文章, 第一行的一级标题作为文章标题
+    CreatePost {
+        file: PathBuf,
+        /// 覆盖从文件中读取的标题
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        draft: bool,
+        #[arg(long)]
+        pinned: bool,
+    },
+    /// 导入Hugo, Jekyll或Hexo等博客的Markdown文件, 读取front matter中的标题, 日期, 标签和slug
+    ImportMarkdown {
+        dir: PathBuf,
+        /// 只解析文件并输出报告, 不写入数据库
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 导入WordPress导出的WXR文件, 包括文章, 分类和标签, 评论以及附件
+    ImportWordpress {
+        file: PathBuf,
+        /// 本地的`wp-content/uploads`目录, 不提供时不导入附件
+        #[arg(long)]
+        uploads: Option<PathBuf>,
+        /// 把页面也作为文章导入, 导入的页面带有`page`标签
+        #[arg(long)]
+        pages: bool,
+        /// 只解析文件并输出报告, 不写入数据库
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// 把已发布的文章导出为静态站点, 默认只重新生成有变化的文章页面
+    ExportSite {
+        output: PathBuf,
+        /// 站点的访问地址, 页面中的链接, 订阅和站点地图都使用这个地址
+        #[arg(long)]
+        base_url: String,
+        /// 忽略上次导出的记录, 重新生成所有页面
+        #[arg(long)]
+        full: bool,
+    },
+    /// 检查数据库中的记录能否正常读取, 以及媒体文件是否存在
+    CheckDb,
+}
+
+pub async fn run(command: Command, config: &ServerConfig) -> anyhow::Result<()> {
+    let repo = repo::open(&config.database).await?;
+    // 开启全文订阅时文章页面和订阅会渲染相同的正文, 通过缓存只渲染一次
+    let repo = match command {
+        Command::ExportSite { .. } => CachedRepo::wrap(repo, &config.cache),
+        _ => repo,
+    };
+    let repo = repo.as_ref();
+    match command {
+        Command::Serve => unreachable!("serve is handled by main"),
+        Command::Install {
+            title,
+            description,
+            password,
+        } => install(repo, title, description, password).await,
+        Command::ResetPassword { password } => reset_password(repo, password).await,
+        Command::Export { output } => {
+            let db = require_surreal(repo)?;
+            let manifest = export_archive(db, &storage::storage(&config.upload)?, &output).await?;
+            for (table, count) in &manifest.tables {
+                println!("{table}: {count} records");
+            }
+            println!(
+                "exported {} media files to {}",
+                manifest.media,
+                output.display()
+            );
+            Ok(())
+        }
+        Command::Import {
+            input,
+            on_conflict,
+            overwrite_settings,
+        } => {
+            let db = require_surreal(repo)?;
+            let storage = storage::storage(&config.upload)?;
+            import(db, &storage, &input, on_conflict, overwrite_settings).await
+        }
+        Command::Backup => {
+            let storage = storage::storage(&config.upload)?;
+            let info = create_backup(require_surreal(repo)?, &storage, &config.backup.dir).await?;
+            println!(
+                "created backup {} in {}",
+                info.name,
+                config.backup.dir.display()
+            );
+            Ok(())
+        }
+        Command::Restore { backup } => {
+            let path = resolve_backup(&config.backup.dir, backup.as_deref())?;
+            let storage = storage::storage(&config.upload)?;
+            println!("restoring from {}", path.display());
+            print_archive_report(&restore_backup(require_surreal(repo)?, &storage, &path).await?)
+        }
+        Command::Migrate => {
+            // 连接数据库时已经执行过迁移
+            println!("database schema is at version {SCHEMA_VERSION}");
+            println!("site settings are at version {SETTINGS_VERSION}");
+            Ok(())
+        }
+        Command::CreatePost {
+            file,
+            title,
+            draft,
+            pinned,
+        } => {
+            let markdown = std::fs::read_to_string(&file)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            let (parsed_title, content) = split_title(&markdown);
+            let title = title
+                .or(parsed_title)
+                .or_else(|| Some(file.file_stem()?.to_string_lossy().into_owned()))
+                .context("post title is required")?;
+            let id = repo
+                .insert_post(NewPost {
+                    title: title.into(),
+                    content,
+                    draft,
+                    pinned,
+                    ..Default::default()
+                })
+                .await?;
+            println!("created post {id}");
+            Ok(())
+        }
+        Command::ImportMarkdown { dir, dry_run } => {
+            print_report(&import_markdown(repo, &dir, dry_run).await?)
+        }
+        Command::ImportWordpress {
+            file,
+            uploads,
+            pages,
+            dry_run,
+        } => {
+            let options = WordpressOptions {
+                uploads: uploads.as_deref(),
+                pages,
+                dry_run,
+            };
+            let storage = storage::storage(&config.upload)?;
+            print_report(&import_wordpress(repo, &storage, &file, options).await?)
+        }
+        Command::ExportSite {
+            output,
+            base_url,
+            full,
+        } => {
+            let storage = storage::storage(&config.upload)?;
+            let report = export_site(repo, &storage, &output, &base_url, full).await?;
+            println!(
+                "rendered: {}, unchanged: {}, written: {}, removed: {}",
+                report.rendered, report.unchanged, report.written, report.removed
+            );
+            Ok(())
+        }
+        Command::CheckDb => check_db(repo, storage::storage(&config.upload)?).await,
+    }
+}
+
+pub async fn install(
+    repo: &dyn Repository,
+    title: String,
+    description: String,
+    password: String,
+) -> anyhow::Result<()> {
+    if password.is_empty() {
+        anyhow::bail!("password must not be empty");
+    }
+    repo.create_config(ConfigRecord {
+        title,
+        description,
+        password,
+        ..Default::default()
+    })
+    .await?;
+    println!("installed");
+    Ok(())
+}
+
+/// 正在运行的服务器只在启动时读取会话纪元, 需要重启后旧的会话才会失效
+pub async fn reset_password(repo: &dyn Repository, password: String) -> anyhow::Result<()> {
+    if repo.is_new_install().await? {
+        anyhow::bail!("blog is not installed");
+    }
+    if password.is_empty() {
+        anyhow::bail!("password must not be empty");
+    }
+    repo.update_password(password).await?;
+    repo.bump_session_epoch().await?;
+    println!("password updated, restart running servers to revoke existing sessions");
+    Ok(())
+}
+
+async fn import(
+    db: &Surreal<Any>,
+    storage: &DynStorage,
+    input: &Path,
+    conflict: Conflict,
+    settings: bool,
+) -> anyhow::Result<()> {
+    print_archive_report(&import_archive(db, storage, input, conflict, settings).await?)
+}
+
+fn print_archive_report(report: &ArchiveReport) -> anyhow::Result<()> {
+    println!(
+        "created: {}, overwritten: {}, renamed: {}, skipped: {}, media files: {}",
+        report.created, report.overwritten, report.renamed, report.skipped, report.media
+    );
+    for (line, err) in &report.failed {
+        println!("error: line {line}: {err}");
+    }
+    if !report.failed.is_empty() {
+        anyhow::bail!("{} records failed to import", report.failed.len());
+    }
+    Ok(())
+}
+
+/// 有失败的条目时返回错误, 方便在脚本中通过退出码判断
+fn print_report(report: &ImportReport) -> anyhow::Result<()> {
+    for (source, outcome) in &report.entries {
+        match outcome {
+            Outcome::Imported(Some(id)) => println!("imported: {source} -> {id}"),
+            Outcome::Imported(None) => println!("would import: {source}"),
+            Outcome::Skipped(reason) => println!("skipped: {source}: {reason}"),
+            Outcome::Failed(err) => println!("error: {source}: {err}"),
+        }
+    }
+    println!(
+        "imported: {}, skipped: {}, failed: {}",
+        report.imported(),
+        report.skipped(),
+        report.failed()
+    );
+    if report.failed() > 0 {
+        anyhow::bail!("{} entries failed to import", report.failed());
+    }
+    Ok(())
+}
+
+/// 发现问题时返回错误, 方便在脚本中通过退出码判断
+pub async fn check_db(repo: &dyn Repository, storage: DynStorage) -> anyhow::Result<()> {
+    let mut problems = 0;
+    if repo.is_new_install().await? {
+        println!("warn: blog is not installed");
+    } else if let Err(err) = repo.query_config().await {
+        println!("error: failed to read site settings: {err:#}");
+        problems += 1;
+    } else {
+        println!("ok: site settings");
+    }
+
+    match repo.query_all_posts().await {
+        Ok(posts) => println!("ok: {} posts", posts.len()),
+        Err(err) => {
+            println!("error: failed to read posts: {err:#}");
+            problems += 1;
+        }
+    }
+
+    let mut page = 0;
+    let mut media_count = 0;
+    loop {
+        let media = match repo.query_media_by_page(page, 100).await {
+            Ok(media) => media,
+            Err(err) => {
+                println!("error: failed to read media: {err:#}");
+                problems += 1;
+                break;
+            }
+        };
+        for media in &media {
+            if !storage.exists(&media.hash).await? {
+                println!("error: file of media {} is missing", media.id);
+                problems += 1;
+            }
+        }
+        media_count += media.len();
+        if media.len() < 100 {
+            println!("ok: {media_count} media");
+            break;
+        }
+        page += 1;
+    }
+
+    if problems > 0 {
+        anyhow::bail!("{problems} problems found");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use smol_str::SmolStr;
+
+    use crate::{
+        db::{
+            archive::{ARCHIVE_VERSION, Conflict, export_archive, import_archive},
+            db,
+            model::{
+                comment::{NewComment, create_comment, query_comments_by_post},
+                config::{
+                    ConfigRecordOption, is_new_install, query_config, update_config,
+                    verify_password,
+                },
+                media::{NewMedia, create_media},
+                post::{PostRecordOption, create_post, query_all_posts, query_post, update_post},
+                session::query_session_epoch,
+            },
+            test_db,
+        },
+        storage::{DynStorage, MemoryStorage, Storage, TempDir},
+    };
+
+    use super::{check_db, install, reset_password};
+
+    #[tokio::test]
+    async fn test_admin_commands() -> anyhow::Result<()> {
+        let db = db(Some("mem://".to_owned())).await?;
+        assert!(reset_password(&db, "new".to_owned()).await.is_err());
+
+        install(&db, "cli".to_owned(), "".to_owned(), "pwd".to_owned()).await?;
+        assert!(!is_new_install(&db).await?);
+        assert!(
+            install(&db, "cli".to_owned(), "".to_owned(), "pwd".to_owned())
+                .await
+                .is_err()
+        );
+
+        reset_password(&db, "new".to_owned()).await?;
+        assert!(verify_password(&db, "new".to_owned()).await?);
+        assert_eq!(query_session_epoch(&db).await?, 1);
+
+        let storage = Arc::new(MemoryStorage::default());
+        check_db(&db, storage.clone()).await?;
+        create_media(
+            &db,
+            NewMedia {
+                filename: "a.txt".into(),
+                mime: "text/plain".into(),
+                size: 1,
+                width: None,
+                height: None,
+                hash: "abcdef".into(),
+            },
+        )
+        .await?;
+        assert!(check_db(&db, storage.clone()).await.is_err());
+        storage.put("abcdef", b"a".to_vec()).await?;
+        check_db(&db, storage).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive() -> anyhow::Result<()> {
+        let db = test_db().await?;
+        let storage: DynStorage = Arc::new(MemoryStorage::default());
+        let id = create_post(&db, "a".into(), "content".into(), false, false).await?;
+        create_post(&db, "b".into(), "content".into(), true, false).await?;
+        create_media(
+            &db,
+            NewMedia {
+                filename: "a.txt".into(),
+                mime: "text/plain".into(),
+                size: 1,
+                width: None,
+                height: None,
+                hash: "abcdef".into(),
+            },
+        )
+        .await?;
+        storage.put("abcdef", b"a".to_vec()).await?;
+
+        let dir = TempDir::new("bulog-archive");
+        let manifest = export_archive(&db, &storage, &dir).await?;
+        assert_eq!(manifest.version, ARCHIVE_VERSION);
+        assert_eq!(manifest.tables["post"], 2);
+        assert_eq!(manifest.media, 1);
+
+        let restored = crate::db::db(Some("mem://".to_owned())).await?;
+        let restored_storage: DynStorage = Arc::new(MemoryStorage::default());
+        let report =
+            import_archive(&restored, &restored_storage, &dir, Conflict::Skip, false).await?;
+        assert_eq!(report.created, 4);
+        assert!(report.failed.is_empty());
+        assert_eq!(query_config(&restored).await?.title, "bulog");
+        let original = query_post(&db, id.clone()).await?.unwrap();
+        let post = query_post(&restored, id.clone()).await?.unwrap();
+        assert_eq!(post.created_time, original.created_time);
+        check_db(&restored, restored_storage.clone()).await?;
+
+        let report =
+            import_archive(&restored, &restored_storage, &dir, Conflict::Skip, false).await?;
+        assert_eq!((report.created, report.skipped), (0, 4));
+
+        update_post(
+            &restored,
+            id.clone(),
+            PostRecordOption {
+                title: Some("changed".into()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let report =
+            import_archive(&restored, &restored_storage, &dir, Conflict::Rename, false).await?;
+        assert_eq!((report.renamed, report.skipped), (1, 3));
+        assert_eq!(query_all_posts(&restored).await?.len(), 3);
+        assert_eq!(
+            query_post(&restored, id.clone()).await?.unwrap().title,
+            "changed"
+        );
+
+        let report = import_archive(
+            &restored,
+            &restored_storage,
+            &dir,
+            Conflict::Overwrite,
+            false,
+        )
+        .await?;
+        assert_eq!((report.overwritten, report.skipped), (3, 1));
+        assert_eq!(query_post(&restored, id).await?.unwrap().title, "a");
+
+        std::fs::write(
+            dir.join("manifest.json"),
+            r#"{"format":"bulog-archive","version":99,"bulog_version":"","created_at":0,"tables":{},"media":0}"#,
+        )?;
+        assert!(
+            import_archive(&restored, &restored_storage, &dir, Conflict::Skip, false)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive_conflicts() -> anyhow::Result<()> {
+        let db = test_db().await?;
+        let storage: DynStorage = Arc::new(MemoryStorage::default());
+        let post = create_post(&db, "a".into(), "content".into(), false, false).await?;
+        let comment = create_comment(&db, new_comment(&post, None)).await?;
+        let reply = create_comment(&db, new_comment(&post, Some(comment.clone()))).await?;
+        create_media(
+            &db,
+            NewMedia {
+                filename: "a.txt".into(),
+                mime: "text/plain".into(),
+                size: 1,
+                width: None,
+                height: None,
+                hash: "abcdef".into(),
+            },
+        )
+        .await?;
+        storage.put("abcdef", b"a".to_vec()).await?;
+        let dir = TempDir::new("bulog-archive");
+        export_archive(&db, &storage, &dir).await?;
+
+        let restored = crate::db::db(Some("mem://".to_owned())).await?;
+        let restored_storage: DynStorage = Arc::new(MemoryStorage::default());
+        import_archive(&restored, &restored_storage, &dir, Conflict::Skip, false).await?;
+        update_post(
+            &restored,
+            post.clone(),
+            PostRecordOption {
+                title: Some("changed".into()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        update_config(
+            &restored,
+            ConfigRecordOption {
+                title: Some("restored".to_owned()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        restored
+            .query("UPDATE type::thing('comment', $comment) SET content = 'edited'")
+            .query("DELETE type::thing('comment', $reply)")
+            .query("UPDATE media SET filename = 'b.txt'")
+            .bind(("comment", comment.clone()))
+            .bind(("reply", reply.clone()))
+            .await?
+            .check()?;
+
+        // 重命名的媒体和已有的记录hash相同, 跳过而不是违反唯一索引
+        let report =
+            import_archive(&restored, &restored_storage, &dir, Conflict::Rename, false).await?;
+        assert!(report.failed.is_empty());
+        assert_eq!((report.renamed, report.created), (2, 1));
+        let renamed = query_all_posts(&restored)
+            .await?
+            .into_iter()
+            .find(|record| record.id != post)
+            .unwrap();
+        let comments = query_comments_by_post(&restored, renamed.id).await?;
+        assert_eq!(comments.len(), 2);
+        let renamed_comment = comments.iter().find(|c| c.parent.is_none()).unwrap();
+        assert_ne!(renamed_comment.id, comment);
+        let imported_reply = comments.iter().find(|c| c.id == reply).unwrap();
+        assert_eq!(imported_reply.parent, Some(renamed_comment.id.clone()));
+
+        import_archive(
+            &restored,
+            &restored_storage,
+            &dir,
+            Conflict::Overwrite,
+            false,
+        )
+        .await?;
+        assert_eq!(query_config(&restored).await?.title, "restored");
+        import_archive(
+            &restored,
+            &restored_storage,
+            &dir,
+            Conflict::Overwrite,
+            true,
+        )
+        .await?;
+        assert_eq!(query_config(&restored).await?.title, "bulog");
+
+        Ok(())
+    }
+
+    fn new_comment(post: &str, parent: Option<SmolStr>) -> NewComment {
+        NewComment {
+            post: post.into(),
+            parent,
+            author: "author".into(),
+            email: "".into(),
+            url: "".into(),
+            content: "comment".into(),
+            created_time: None,
+            approved: true,
+        }
+    }
+}
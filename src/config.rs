@@ -0,0 +1,812 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Cli;
+
+/// 默认读取的配置文件, 不存在时只使用默认值和环境变量
+const DEFAULT_CONFIG_FILE: &str = "./bulog.toml";
+
+/// 进程级别的配置, 优先级从低到高依次为: 默认值, 配置文件, 环境变量, 命令行参数
+///
+/// 博客本身的设置保存在数据库中, 见`db::model::config`
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub server: ListenConfig,
+    pub database: DatabaseConfig,
+    pub session: SessionConfig,
+    pub upload: UploadConfig,
+    pub backup: BackupConfig,
+    pub cache: CacheConfig,
+    pub log: LogConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    pub bind: String,
+    /// 启用tls时额外监听的http地址, 所有请求都会被重定向到https
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_bind: Option<String>,
+    /// 前端文件目录, 未配置时使用编译进程序的文件或`./web/dist`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsFiles>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acme: Option<AcmeConfig>,
+    pub cors: CorsConfig,
+    /// 按`Accept-Encoding`压缩json和文本响应
+    pub compression: bool,
+    /// 在`/metrics`提供Prometheus格式的运行指标
+    ///
+    /// 这个地址不需要登录, 开启后应在反向代理或防火墙中限制访问
+    pub metrics: bool,
+    /// 在`/v1/docs`提供接口文档页面, 页面的脚本从CDN加载
+    pub api_docs: bool,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        ListenConfig {
+            bind: "0.0.0.0:8686".to_owned(),
+            http_bind: None,
+            web_dir: None,
+            tls: None,
+            acme: None,
+            cors: CorsConfig::default(),
+            compression: true,
+            metrics: false,
+            api_docs: false,
+        }
+    }
+}
+
+/// 手动管理的证书, 文件更新后自动重新加载
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AcmeConfig {
+    pub domains: Vec<String>,
+    pub contacts: Vec<String>,
+    /// ACME服务的目录地址, 测试时可以指向本地的Pebble, 并通过`SSL_CERT_FILE`信任它的根证书
+    pub directory: String,
+    /// 证书和账户密钥的缓存目录, 避免每次启动都重新申请
+    pub cache: PathBuf,
+    pub challenge: AcmeChallenge,
+}
+
+impl Default for AcmeConfig {
+    fn default() -> Self {
+        AcmeConfig {
+            domains: Vec::new(),
+            contacts: Vec::new(),
+            directory: salvo::conn::acme::LETS_ENCRYPT_PRODUCTION.to_owned(),
+            cache: "./acme".into(),
+            challenge: AcmeChallenge::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AcmeChallenge {
+    /// 需要同时配置`server.http_bind`并且能从80端口访问
+    #[serde(rename = "http-01")]
+    Http01,
+    #[default]
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+}
+
+/// 前端与接口不同源时的跨域设置, 没有配置允许的源时不启用
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// 例如`["http://localhost:8080", "https://blog.example"]`
+    pub origins: Vec<String>,
+    /// 允许携带`bulog` cookie, 与`*`源互斥
+    pub credentials: bool,
+    /// 预检结果的缓存时间, 单位秒
+    pub max_age: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            origins: Vec::new(),
+            credentials: true,
+            max_age: 86400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// 嵌入式引擎例如`rocksdb://./db.rocks`, 或者远程服务器例如`ws://127.0.0.1:8000`
+    pub endpoint: String,
+    pub namespace: String,
+    pub database: String,
+    /// 启动时连接远程服务器失败的重试次数, 每次重试的间隔翻倍, 最长30秒
+    pub connect_retries: u32,
+    /// 远程服务器的登录凭据, 嵌入式引擎不需要
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<DatabaseAuth>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            endpoint: crate::db::default_endpoint(),
+            namespace: "bulog".to_owned(),
+            database: "blog".to_owned(),
+            connect_retries: 5,
+            auth: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseAuth {
+    #[serde(default)]
+    pub level: AuthLevel,
+    pub username: String,
+    /// `--print-config`输出的配置中不包含密码
+    #[serde(serialize_with = "redact")]
+    pub password: String,
+    /// `record`级别使用的`DEFINE ACCESS`名称, 用户名和密码作为`$username`和`$password`传入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<String>,
+}
+
+impl std::fmt::Debug for DatabaseAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatabaseAuth")
+            .field("level", &self.level)
+            .field("username", &self.username)
+            .field("password", &REDACTED)
+            .field("access", &self.access)
+            .finish()
+    }
+}
+
+/// 以天为单位的配置项的上限, 换算成时间时不会溢出
+const MAX_DAYS: u64 = 100 * 365;
+
+/// 代替敏感字段输出的内容
+const REDACTED: &str = "<redacted>";
+
+fn redact<S: serde::Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
+
+/// 登录的用户级别, 对应SurrealDB中定义用户或访问方式的位置
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthLevel {
+    #[default]
+    Root,
+    Namespace,
+    Database,
+    Record,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// 登录状态的有效期, 单位天
+    pub ttl_days: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig { ttl_days: 30 }
+    }
+}
+
+impl SessionConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_days.saturating_mul(24 * 3600))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadConfig {
+    /// 本地存储后端保存上传文件的目录
+    pub dir: PathBuf,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            dir: "./uploads".into(),
+        }
+    }
+}
+
+/// 服务器运行时的在线备份, 每份备份都是一个`export`格式的目录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// 保存备份的目录
+    pub dir: PathBuf,
+    /// 自动备份的间隔, 单位小时, 为0时只能手动备份
+    pub interval_hours: u64,
+    /// 最多保留的备份数量, 为0时不限制
+    pub keep: usize,
+    /// 删除超过这个天数的备份, 为0时不限制, 最新的一份备份总是保留
+    pub max_age_days: u64,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            dir: "./backups".into(),
+            interval_hours: 0,
+            keep: 7,
+            max_age_days: 0,
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn interval(&self) -> Option<Duration> {
+        (self.interval_hours > 0)
+            .then(|| Duration::from_secs(self.interval_hours.saturating_mul(3600)))
+    }
+}
+
+/// 服务器进程内的查询缓存
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// 每一类缓存最多保存的条目数, 为0时关闭缓存
+    pub max_entries: usize,
+    /// 每一类缓存最多占用的字节数, 超过这个大小的单个条目不会被缓存
+    pub max_bytes: usize,
+    /// 缓存的有效期, 单位秒, 用于发现其他进程对数据库的修改
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_entries: 1024,
+            max_bytes: 16 << 20,
+            ttl_secs: 300,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `tracing_subscriber::EnvFilter`的语法
+    pub filter: String,
+    pub format: LogFormat,
+    /// span结束时输出一条带耗时的日志, 用于追踪慢请求和慢查询
+    pub spans: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "warn,bulog=info".to_owned(),
+            format: LogFormat::Text,
+            spans: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// 每行一个json对象, 方便日志收集系统解析
+    Json,
+}
+
+/// 通过OTLP导出trace, 需要启用`otel` feature
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// collector的地址, 例如`http://localhost:4317`, 为空时不导出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    pub protocol: OtlpProtocol,
+    pub service_name: String,
+    /// 没有上游trace时的采样比例, 请求中带有`traceparent`时跟随上游的决定
+    pub sample_ratio: f64,
+    /// 额外的resource属性, 例如`deployment.environment = "production"`
+    pub resource: BTreeMap<String, String>,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            endpoint: None,
+            protocol: OtlpProtocol::Grpc,
+            service_name: "bulog".to_owned(),
+            sample_ratio: 1.0,
+            resource: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    Grpc,
+    /// http/protobuf, 地址没有路径时会加上`/v1/traces`
+    Http,
+}
+
+impl ServerConfig {
+    pub fn load(cli: &Cli) -> anyhow::Result<ServerConfig> {
+        let mut config = match &cli.config {
+            Some(path) => ServerConfig::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                ServerConfig::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => ServerConfig::default(),
+        };
+        config.apply_env(|key| std::env::var(key).ok())?;
+        config.apply_cli(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<ServerConfig> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("failed to parse config file {}", path.display()))
+    }
+
+    /// 兼容之前只用环境变量配置的方式, `var`为了测试时不修改进程的环境变量
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        // 直接解析为目标类型, 超出范围的值报错而不是被截断
+        fn parse<T>(var: impl Fn(&str) -> Option<String>, key: &str) -> anyhow::Result<Option<T>>
+        where
+            T: FromStr,
+            T::Err: std::error::Error + Send + Sync + 'static,
+        {
+            var(key)
+                .map(|value| {
+                    value
+                        .parse()
+                        .with_context(|| format!("{key} must be a number, got `{value}`"))
+                })
+                .transpose()
+        }
+        let list = |value: String| -> Vec<String> {
+            value
+                .split(',')
+                .map(|item| item.trim().to_owned())
+                .filter(|item| !item.is_empty())
+                .collect()
+        };
+
+        let server = &mut self.server;
+        if let Some(bind) = var("BU_BIND") {
+            server.bind = bind;
+        }
+        if let Some(http_bind) = var("BU_HTTP_BIND") {
+            server.http_bind = Some(http_bind);
+        }
+        if let Some(web_dir) = var("BU_WEB_DIR") {
+            server.web_dir = Some(web_dir.into());
+        }
+        if let (Some(cert), Some(key)) = (var("BU_TLS_CERT"), var("BU_TLS_KEY")) {
+            server.tls = Some(TlsFiles {
+                cert: cert.into(),
+                key: key.into(),
+            });
+        }
+        if let Some(domains) = var("BU_ACME_DOMAINS") {
+            server.acme.get_or_insert_with(Default::default).domains = list(domains);
+        }
+        if let Some(acme) = &mut server.acme {
+            if let Some(contacts) = var("BU_ACME_CONTACTS") {
+                acme.contacts = list(contacts);
+            }
+            if let Some(directory) = var("BU_ACME_DIRECTORY") {
+                acme.directory = directory;
+            }
+            if let Some(cache) = var("BU_ACME_CACHE") {
+                acme.cache = cache.into();
+            }
+            acme.challenge = match var("BU_ACME_CHALLENGE").as_deref() {
+                Some("http-01") => AcmeChallenge::Http01,
+                Some("tls-alpn-01") => AcmeChallenge::TlsAlpn01,
+                Some(other) => anyhow::bail!("unknown acme challenge type: {other}"),
+                None => acme.challenge,
+            };
+        }
+        if let Some(origins) = var("BU_CORS_ORIGINS") {
+            server.cors.origins = list(origins);
+        }
+        if let Some(credentials) = var("BU_CORS_CREDENTIALS") {
+            server.cors.credentials = credentials != "false";
+        }
+        if let Some(max_age) = parse(&var, "BU_CORS_MAX_AGE")? {
+            server.cors.max_age = max_age;
+        }
+        if let Some(compression) = var("BU_COMPRESSION") {
+            server.compression = compression != "false";
+        }
+        if let Some(metrics) = var("BU_METRICS") {
+            server.metrics = metrics != "false";
+        }
+        if let Some(api_docs) = var("BU_API_DOCS") {
+            server.api_docs = api_docs != "false";
+        }
+
+        let database = &mut self.database;
+        if let Some(endpoint) = var("BU_ENDPOINT") {
+            database.endpoint = endpoint;
+        }
+        if let Some(namespace) = var("BU_DB_NAMESPACE") {
+            database.namespace = namespace;
+        }
+        if let Some(name) = var("BU_DB_DATABASE") {
+            database.database = name;
+        }
+        if let Some(retries) = parse(&var, "BU_DB_CONNECT_RETRIES")? {
+            database.connect_retries = retries;
+        }
+        if let (Some(username), Some(password)) = (var("BU_DB_USERNAME"), var("BU_DB_PASSWORD")) {
+            database.auth = Some(DatabaseAuth {
+                level: AuthLevel::Root,
+                username,
+                password,
+                access: None,
+            });
+        }
+        if let Some(auth) = &mut database.auth {
+            auth.level = match var("BU_DB_AUTH_LEVEL").as_deref() {
+                Some("root") => AuthLevel::Root,
+                Some("namespace") => AuthLevel::Namespace,
+                Some("database") => AuthLevel::Database,
+                Some("record") => AuthLevel::Record,
+                Some(other) => anyhow::bail!("unknown database auth level: {other}"),
+                None => auth.level,
+            };
+            if let Some(access) = var("BU_DB_ACCESS") {
+                auth.access = Some(access);
+            }
+        }
+        if let Some(ttl_days) = parse(&var, "BU_SESSION_TTL_DAYS")? {
+            self.session.ttl_days = ttl_days;
+        }
+        if let Some(dir) = var("BU_UPLOAD_DIR") {
+            self.upload.dir = dir.into();
+        }
+        if let Some(dir) = var("BU_BACKUP_DIR") {
+            self.backup.dir = dir.into();
+        }
+        if let Some(interval_hours) = parse(&var, "BU_BACKUP_INTERVAL_HOURS")? {
+            self.backup.interval_hours = interval_hours;
+        }
+        if let Some(keep) = parse(&var, "BU_BACKUP_KEEP")? {
+            self.backup.keep = keep;
+        }
+        if let Some(max_age_days) = parse(&var, "BU_BACKUP_MAX_AGE_DAYS")? {
+            self.backup.max_age_days = max_age_days;
+        }
+        if let Some(max_entries) = parse(&var, "BU_CACHE_MAX_ENTRIES")? {
+            self.cache.max_entries = max_entries;
+        }
+        if let Some(max_bytes) = parse(&var, "BU_CACHE_MAX_BYTES")? {
+            self.cache.max_bytes = max_bytes;
+        }
+        if let Some(ttl_secs) = parse(&var, "BU_CACHE_TTL_SECS")? {
+            self.cache.ttl_secs = ttl_secs;
+        }
+        if let Some(filter) = var("BU_LOG") {
+            self.log.filter = filter;
+        }
+        self.log.format = match var("BU_LOG_FORMAT").as_deref() {
+            Some("text") => LogFormat::Text,
+            Some("json") => LogFormat::Json,
+            Some(other) => anyhow::bail!("unknown log format: {other}"),
+            None => self.log.format,
+        };
+        if let Some(spans) = var("BU_LOG_SPANS") {
+            self.log.spans = spans != "false";
+        }
+        let telemetry = &mut self.telemetry;
+        if let Some(endpoint) = var("BU_OTLP_ENDPOINT") {
+            telemetry.endpoint = Some(endpoint);
+        }
+        telemetry.protocol = match var("BU_OTLP_PROTOCOL").as_deref() {
+            Some("grpc") => OtlpProtocol::Grpc,
+            Some("http") => OtlpProtocol::Http,
+            Some(other) => anyhow::bail!("unknown otlp protocol: {other}"),
+            None => telemetry.protocol,
+        };
+        if let Some(service_name) = var("BU_OTLP_SERVICE_NAME") {
+            telemetry.service_name = service_name;
+        }
+        if let Some(ratio) = var("BU_OTLP_SAMPLE_RATIO") {
+            telemetry.sample_ratio = ratio
+                .parse()
+                .with_context(|| format!("BU_OTLP_SAMPLE_RATIO must be a number, got `{ratio}`"))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(bind) = &cli.bind {
+            self.server.bind = bind.clone();
+        }
+        if let Some(endpoint) = &cli.endpoint {
+            self.database.endpoint = endpoint.clone();
+        }
+        if let Some(log) = &cli.log {
+            self.log.filter = log.clone();
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let server = &self.server;
+        validate_bind("server.bind", &server.bind)?;
+        if let Some(http_bind) = &server.http_bind {
+            validate_bind("server.http_bind", http_bind)?;
+            if server.tls.is_none() && server.acme.is_none() {
+                anyhow::bail!("server.http_bind only works with server.tls or server.acme");
+            }
+        }
+        if server.tls.is_some() && server.acme.is_some() {
+            anyhow::bail!("server.tls and server.acme can not be used together");
+        }
+        if let Some(acme) = &server.acme {
+            if acme.domains.is_empty() {
+                anyhow::bail!("server.acme.domains must not be empty");
+            }
+            if acme.challenge == AcmeChallenge::Http01 && server.http_bind.is_none() {
+                anyhow::bail!("acme http-01 challenge requires server.http_bind");
+            }
+        }
+        if let Some(origin) = server
+            .cors
+            .origins
+            .iter()
+            .find(|origin| *origin != "*" && !origin.starts_with("http"))
+        {
+            anyhow::bail!("invalid cors origin `{origin}`, expected `*` or `scheme://host[:port]`");
+        }
+        let database = &self.database;
+        if database.endpoint.is_empty() {
+            anyhow::bail!("database.endpoint must not be empty");
+        }
+        if database.namespace.is_empty() || database.database.is_empty() {
+            anyhow::bail!("database.namespace and database.database must not be empty");
+        }
+        if let Some(auth) = &database.auth
+            && auth.level == AuthLevel::Record
+            && auth.access.is_none()
+        {
+            anyhow::bail!("database.auth.access is required for record level auth");
+        }
+        if self.session.ttl_days == 0 || self.session.ttl_days > MAX_DAYS {
+            anyhow::bail!("session.ttl_days must be between 1 and {MAX_DAYS}");
+        }
+        if self.backup.dir.as_os_str().is_empty() {
+            anyhow::bail!("backup.dir must not be empty");
+        }
+        if self.backup.interval_hours > MAX_DAYS * 24 {
+            anyhow::bail!("backup.interval_hours must not exceed {}", MAX_DAYS * 24);
+        }
+        if self.backup.max_age_days > MAX_DAYS {
+            anyhow::bail!("backup.max_age_days must not exceed {MAX_DAYS}");
+        }
+        let telemetry = &self.telemetry;
+        if telemetry.endpoint.is_some() && !cfg!(feature = "otel") {
+            anyhow::bail!("telemetry.endpoint requires the `otel` feature");
+        }
+        if !(0.0..=1.0).contains(&telemetry.sample_ratio) {
+            anyhow::bail!("telemetry.sample_ratio must be between 0 and 1");
+        }
+        if telemetry.service_name.is_empty() {
+            anyhow::bail!("telemetry.service_name must not be empty");
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.log.filter)
+            .with_context(|| format!("invalid log.filter `{}`", self.log.filter))?;
+        Ok(())
+    }
+
+    /// 端口号, 用于把http请求重定向到https
+    pub fn port(&self) -> u16 {
+        self.server
+            .bind
+            .rsplit_once(':')
+            .and_then(|(_, port)| port.parse().ok())
+            .unwrap_or(443)
+    }
+}
+
+fn validate_bind(name: &str, bind: &str) -> anyhow::Result<()> {
+    match bind.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+        _ => anyhow::bail!("invalid {name} `{bind}`, expected `host:port`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use clap::Parser;
+
+    use super::{AcmeChallenge, AuthLevel, LogFormat, OtlpProtocol, ServerConfig};
+    use crate::cli::Cli;
+
+    #[test]
+    fn test_layers() {
+        let mut config: ServerConfig = toml::from_str(
+            r#"
+            [server]
+            bind = "127.0.0.1:8080"
+
+            [server.cors]
+            origins = ["http://localhost:3000"]
+
+            [database]
+            endpoint = "rocksdb://./data"
+
+            [database.auth]
+            level = "database"
+            username = "bulog"
+            password = "secret"
+
+            [session]
+            ttl_days = 7
+
+            [backup]
+            interval_hours = 24
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.bind, "127.0.0.1:8080");
+        assert_eq!(config.server.cors.max_age, 86400);
+        assert_eq!(config.upload.dir.to_str(), Some("./uploads"));
+
+        let env = HashMap::from([
+            ("BU_BIND", "127.0.0.1:9090"),
+            ("BU_ACME_DOMAINS", "blog.example, www.blog.example"),
+            ("BU_ACME_CHALLENGE", "http-01"),
+            ("BU_HTTP_BIND", "0.0.0.0:80"),
+            ("BU_LOG", "debug"),
+            ("BU_BACKUP_KEEP", "3"),
+            ("BU_CACHE_MAX_ENTRIES", "0"),
+            ("BU_LOG_FORMAT", "json"),
+        ]);
+        config
+            .apply_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap();
+        config.apply_cli(&Cli::parse_from(["bulog", "--endpoint", "mem://"]));
+        config.validate().unwrap();
+
+        assert_eq!(config.server.bind, "127.0.0.1:9090");
+        assert_eq!(config.port(), 9090);
+        let acme = config.server.acme.as_ref().unwrap();
+        assert_eq!(acme.domains, ["blog.example", "www.blog.example"]);
+        assert_eq!(acme.challenge, AcmeChallenge::Http01);
+        assert_eq!(config.database.endpoint, "mem://");
+        assert_eq!(config.session.ttl_days, 7);
+        assert_eq!(config.log.filter, "debug");
+        assert_eq!(config.log.format, LogFormat::Json);
+        let auth = config.database.auth.as_ref().unwrap();
+        assert_eq!(auth.level, AuthLevel::Database);
+        assert_eq!(auth.username, "bulog");
+        assert_eq!(config.database.namespace, "bulog");
+        assert_eq!(config.backup.interval_hours, 24);
+        assert_eq!(config.backup.keep, 3);
+        assert_eq!(config.backup.dir.to_str(), Some("./backups"));
+        assert_eq!(config.cache.max_entries, 0);
+        assert_eq!(config.cache.ttl_secs, 300);
+
+        let printed = toml::to_string_pretty(&config).unwrap();
+        assert!(!printed.contains("secret"));
+        assert!(!format!("{config:?}").contains("secret"));
+        let reparsed: ServerConfig = toml::from_str(&printed).unwrap();
+        assert_eq!(reparsed.server.bind, "127.0.0.1:9090");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let err = toml::from_str::<ServerConfig>("[server]\nbnid = \"0.0.0.0:80\"").unwrap_err();
+        assert!(err.to_string().contains("unknown field `bnid`"));
+
+        let mut config = ServerConfig::default();
+        config.server.bind = "8686".to_owned();
+        assert!(config.validate().is_err());
+
+        let mut config = ServerConfig::default();
+        let env = HashMap::from([
+            ("BU_ACME_DOMAINS", "blog.example"),
+            ("BU_ACME_CHALLENGE", "http-01"),
+        ]);
+        config
+            .apply_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap();
+        let err = config.validate().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "acme http-01 challenge requires server.http_bind"
+        );
+
+        let mut config = ServerConfig::default();
+        let env = HashMap::from([
+            ("BU_DB_USERNAME", "bulog"),
+            ("BU_DB_PASSWORD", "secret"),
+            ("BU_DB_AUTH_LEVEL", "record"),
+        ]);
+        config
+            .apply_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap();
+        assert!(config.validate().is_err());
+
+        let mut config = ServerConfig::default();
+        let env = HashMap::from([
+            ("BU_OTLP_PROTOCOL", "http"),
+            ("BU_OTLP_SAMPLE_RATIO", "1.5"),
+        ]);
+        config
+            .apply_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.telemetry.protocol, OtlpProtocol::Http);
+        assert!(config.validate().is_err());
+
+        let mut config = ServerConfig::default();
+        config.backup.max_age_days = u64::MAX;
+        assert!(config.validate().is_err());
+
+        let mut config = ServerConfig::default();
+        let env = HashMap::from([("BU_SESSION_TTL_DAYS", "18446744073709551615")]);
+        config
+            .apply_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.session.ttl().as_secs(), u64::MAX);
+        assert!(config.validate().is_err());
+
+        let mut config = ServerConfig::default();
+        let env = HashMap::from([("BU_CORS_MAX_AGE", "one day")]);
+        assert!(
+            config
+                .apply_env(|key| env.get(key).map(|value| value.to_string()))
+                .is_err()
+        );
+
+        // 超出目标类型范围的值不会被截断
+        let mut config = ServerConfig::default();
+        let env = HashMap::from([("BU_DB_CONNECT_RETRIES", "4294967297")]);
+        let err = config
+            .apply_env(|key| env.get(key).map(|value| value.to_string()))
+            .unwrap_err();
+        assert!(err.to_string().contains("BU_DB_CONNECT_RETRIES"));
+    }
+}
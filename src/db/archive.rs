@@ -0,0 +1,422 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Surreal, engine::any::Any};
+
+use crate::{
+    db::{check_transaction, model::config::migrate_config, schema::META_TABLE},
+    nano_id::nanoid,
+    storage::DynStorage,
+};
+
+/// 导出格式的版本, 格式发生不兼容的变化时递增
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const ARCHIVE_FORMAT: &str = "bulog-archive";
+const MANIFEST_FILE: &str = "manifest.json";
+const RECORDS_FILE: &str = "records.jsonl";
+const MEDIA_DIR: &str = "media";
+
+/// 只有一条固定id记录的表, 导入时无法重命名
+const SINGLETON_TABLES: [&str; 2] = ["config", "secret"];
+
+/// 导出的目录结构:
+/// - `manifest.json`: 格式版本和统计信息
+/// - `records.jsonl`: 所有表的记录, 每行一条
+/// - `media/<hash>`: 上传的原始文件, 缩略图会在访问时重新生成
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    /// 导出时的程序版本, 只用于排查问题
+    pub bulog_version: String,
+    /// unix时间戳, 单位秒
+    pub created_at: u64,
+    /// 每张表导出的记录数
+    pub tables: BTreeMap<String, usize>,
+    pub media: usize,
+}
+
+/// 导出文件中的一行, 每行一条记录
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveLine {
+    pub table: String,
+    /// SurrealQL格式的记录, 保留日期和记录id等json无法表示的类型
+    pub record: String,
+}
+
+/// 导入的记录id已经存在时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Conflict {
+    /// 保留数据库中已有的记录
+    Skip,
+    /// 用导入的记录替换已有的记录, `config`和`secret`只在明确指定时替换
+    Overwrite,
+    /// 已有的记录与导入的不同时使用新的id导入, `config`等只有一条记录的表仍然跳过
+    Rename,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub created: usize,
+    pub overwritten: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+    /// 行号和错误信息, 失败的记录不影响其他记录的导入
+    pub failed: Vec<(usize, String)>,
+    pub media: usize,
+}
+
+/// 导出的表, 不包含记录数据库结构版本的表
+pub async fn tables(db: &Surreal<Any>) -> anyhow::Result<Vec<String>> {
+    let tables: Option<BTreeMap<String, String>> =
+        db.query("INFO FOR DB").await?.take((0, "tables"))?;
+    Ok(tables
+        .unwrap_or_default()
+        .into_keys()
+        .filter(|table| table != META_TABLE)
+        .collect())
+}
+
+/// 把数据库和上传的文件导出到`dir`, `dir`不存在时会被创建, 已有的导出会被覆盖
+pub async fn export_archive(
+    db: &Surreal<Any>,
+    storage: &DynStorage,
+    dir: &Path,
+) -> anyhow::Result<Manifest> {
+    tokio::fs::create_dir_all(dir.join(MEDIA_DIR))
+        .await
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+
+    // 查询结果已经全部在内存中, 先写入缓冲区再用tokio::fs写文件, 不阻塞运行时
+    let mut records = Vec::new();
+    let (tables, hashes) = export_records(db, &mut records).await?;
+    tokio::fs::write(dir.join(RECORDS_FILE), records).await?;
+
+    let mut media = 0;
+    for hash in hashes {
+        // 快照之后删除的媒体文件已经不存在, 跳过它不影响其他数据的备份
+        let Some(data) = storage.get(&hash).await? else {
+            tracing::warn!("file {hash} was deleted during export");
+            continue;
+        };
+        tokio::fs::write(dir.join(MEDIA_DIR).join(&hash), data).await?;
+        media += 1;
+    }
+
+    let manifest = Manifest {
+        format: ARCHIVE_FORMAT.to_owned(),
+        version: ARCHIVE_VERSION,
+        bulog_version: env!("CARGO_PKG_VERSION").to_owned(),
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        tables,
+        media,
+    };
+    tokio::fs::write(
+        dir.join(MANIFEST_FILE),
+        sonic_rs::to_string_pretty(&manifest)?,
+    )
+    .await?;
+    Ok(manifest)
+}
+
+/// 导出所有表的记录, 返回每张表导出的记录数和媒体文件的hash
+///
+/// 所有表在同一个事务中读取, 服务器运行时导出也能得到一致的快照
+pub async fn export_records(
+    db: &Surreal<Any>,
+    out: &mut impl Write,
+) -> anyhow::Result<(BTreeMap<String, usize>, Vec<String>)> {
+    let tables = tables(db).await?;
+    let mut query = db
+        .query("BEGIN TRANSACTION;")
+        .query("SELECT VALUE hash FROM media;");
+    for (index, table) in tables.iter().enumerate() {
+        // 在数据库中转换为字符串, 得到的就是SurrealQL格式的记录
+        query = query
+            .query(format!(
+                "SELECT VALUE <string> $this FROM type::table($table{index});"
+            ))
+            .bind((format!("table{index}"), table.clone()));
+    }
+    let mut resp = query.query("COMMIT TRANSACTION;").await?;
+
+    let hashes: Vec<String> = resp.take(0)?;
+    let mut counts = BTreeMap::new();
+    for (index, table) in tables.into_iter().enumerate() {
+        let records: Vec<String> = resp.take(index + 1)?;
+        counts.insert(table.clone(), records.len());
+        for record in records {
+            let line = ArchiveLine {
+                table: table.clone(),
+                record,
+            };
+            writeln!(out, "{}", sonic_rs::to_string(&line)?)?;
+        }
+    }
+    out.flush()?;
+    Ok((counts, hashes))
+}
+
+pub fn read_manifest(dir: &Path) -> anyhow::Result<Manifest> {
+    let path = dir.join(MANIFEST_FILE);
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let manifest: Manifest = sonic_rs::from_str(&content)
+        .with_context(|| format!("invalid manifest {}", path.display()))?;
+    if manifest.format != ARCHIVE_FORMAT {
+        anyhow::bail!("{} is not a bulog archive", dir.display());
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        anyhow::bail!(
+            "archive version {} is newer than supported version {ARCHIVE_VERSION}",
+            manifest.version
+        );
+    }
+    Ok(manifest)
+}
+
+/// 导入`export_archive`导出的目录, 导入后把旧版本的设置升级到当前版本
+pub async fn import_archive(
+    db: &Surreal<Any>,
+    storage: &DynStorage,
+    dir: &Path,
+    conflict: Conflict,
+    settings: bool,
+) -> anyhow::Result<ImportReport> {
+    read_manifest(dir)?;
+    let media = import_media(storage, dir).await?;
+    let records = BufReader::new(File::open(dir.join(RECORDS_FILE))?);
+    let mut report = import_records(db, records, conflict, settings).await?;
+    report.media = media;
+    migrate_config(db).await?;
+    Ok(report)
+}
+
+/// 用导出的目录替换数据库中的所有记录, 数据库恢复到导出时的状态
+///
+/// 清空和写入在同一个事务中执行, 任何一条记录失败时数据库保持不变
+pub async fn replace_archive(
+    db: &Surreal<Any>,
+    storage: &DynStorage,
+    dir: &Path,
+) -> anyhow::Result<ImportReport> {
+    read_manifest(dir)?;
+    let media = import_media(storage, dir).await?;
+
+    let mut query = db.query("BEGIN TRANSACTION;");
+    for (index, table) in tables(db).await?.into_iter().enumerate() {
+        query = query
+            .query(format!("DELETE type::table($table{index});"))
+            .bind((format!("table{index}"), table));
+    }
+    let records = BufReader::new(File::open(dir.join(RECORDS_FILE))?);
+    let mut created = 0;
+    for (index, line) in records.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (table, record) =
+            parse_line(&line).with_context(|| format!("invalid record on line {}", index + 1))?;
+        query = query
+            .query(format!(
+                "CREATE type::thing($record_table{index}, record::id($record{index}.id)) \
+                 CONTENT $record{index};"
+            ))
+            .bind((format!("record_table{index}"), table))
+            .bind((format!("record{index}"), record));
+        created += 1;
+    }
+    check_transaction(&mut query.query("COMMIT TRANSACTION;").await?)?;
+    migrate_config(db).await?;
+    Ok(ImportReport {
+        created,
+        media,
+        ..Default::default()
+    })
+}
+
+/// 先写入文件, 保证导入的媒体记录都能找到对应的文件
+async fn import_media(storage: &DynStorage, dir: &Path) -> anyhow::Result<usize> {
+    let mut media = 0;
+    let media_dir = dir.join(MEDIA_DIR);
+    if media_dir.is_dir() {
+        for entry in std::fs::read_dir(media_dir)? {
+            let entry = entry?;
+            let hash = entry.file_name().to_string_lossy().into_owned();
+            if !storage.exists(&hash).await? {
+                storage.put(&hash, std::fs::read(entry.path())?).await?;
+            }
+            media += 1;
+        }
+    }
+    Ok(media)
+}
+
+/// 逐行写入记录, 单条记录失败时记录到报告中并继续
+///
+/// `settings`为false时`config`和`secret`不会被覆盖, 避免导入时替换掉当前的密码和会话密钥
+pub async fn import_records(
+    db: &Surreal<Any>,
+    input: impl BufRead,
+    conflict: Conflict,
+    settings: bool,
+) -> anyhow::Result<ImportReport> {
+    let mut report = ImportReport::default();
+    let mut renamed = Renamed::default();
+    let mut comments = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let imported = match import_line(db, &line, conflict, settings).await {
+            Ok(imported) => imported,
+            Err(err) => {
+                report.failed.push((index + 1, format!("{err:#}")));
+                continue;
+            }
+        };
+        match imported.result {
+            Imported::Created => report.created += 1,
+            Imported::Overwritten => report.overwritten += 1,
+            Imported::Renamed => report.renamed += 1,
+            Imported::Skipped => report.skipped += 1,
+        }
+        if let Some(id) = imported.id {
+            match (imported.table.as_str(), imported.renamed_from) {
+                ("post", Some(old)) => _ = renamed.post.insert(old, id.clone()),
+                ("comment", Some(old)) => _ = renamed.comment.insert(old, id.clone()),
+                _ => {}
+            }
+            if imported.table == "comment" {
+                comments.push(id);
+            }
+        }
+    }
+    // 评论在文章之前导出, 所有记录写入后再把评论指向重命名后的文章和评论
+    if !comments.is_empty() && (!renamed.post.is_empty() || !renamed.comment.is_empty()) {
+        db.query(
+            r#"
+        FOR $id IN $comments {
+            UPDATE type::thing("comment", $id) SET
+                post = $renamed.post[post] ?? post,
+                parent = IF parent { $renamed.comment[parent] ?? parent } ELSE { parent };
+        };
+    "#,
+        )
+        .bind(("comments", comments))
+        .bind(("renamed", renamed))
+        .await?
+        .check()?;
+    }
+    Ok(report)
+}
+
+/// 导入时重命名的记录, 旧的id到新的id
+#[derive(Default, Serialize)]
+struct Renamed {
+    post: BTreeMap<String, String>,
+    comment: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Imported {
+    Created,
+    Overwritten,
+    Renamed,
+    Skipped,
+}
+
+#[derive(Deserialize)]
+struct ImportedLine {
+    result: Imported,
+    table: String,
+    /// 写入的记录id, 跳过时为空
+    id: Option<String>,
+    renamed_from: Option<String>,
+}
+
+fn parse_line(line: &str) -> anyhow::Result<(String, surrealdb::Value)> {
+    let line: ArchiveLine = sonic_rs::from_str(line)?;
+    let record = line
+        .record
+        .parse()
+        .map_err(|err| anyhow::anyhow!("invalid record: {err}"))?;
+    Ok((line.table, record))
+}
+
+async fn import_line(
+    db: &Surreal<Any>,
+    line: &str,
+    conflict: Conflict,
+    settings: bool,
+) -> anyhow::Result<ImportedLine> {
+    let (table, record) = parse_line(line)?;
+    let singleton = SINGLETON_TABLES.contains(&table.as_str());
+    let conflict = match conflict {
+        Conflict::Rename if singleton => Conflict::Skip,
+        Conflict::Overwrite if singleton && !settings => Conflict::Skip,
+        conflict => conflict,
+    };
+    let imported: Option<ImportedLine> = db
+        .query(
+            r#"
+        LET $id = type::thing($table, record::id($record.id));
+        -- 媒体的hash有唯一索引, 内容相同的文件只保留已有的记录
+        LET $same_file = IF $table = "media" {
+            (SELECT VALUE id FROM media WHERE hash = $record.hash)
+        } ELSE {
+            []
+        };
+        LET $result = IF $same_file[WHERE $this != $id] {
+            { result: "skipped" }
+        } ELSE IF !record::exists($id) {
+            CREATE $id CONTENT $record;
+            { result: "created", id: $id }
+        } ELSE IF $conflict = "overwrite" {
+            UPDATE $id CONTENT $record;
+            { result: "overwritten", id: $id }
+        } ELSE IF $conflict = "rename" AND !$same_file AND $id.* != $record {
+            LET $new = type::thing($table, $new_id);
+            CREATE $new CONTENT object::from_entries(object::entries($record)[WHERE $this[0] != "id"]);
+            { result: "renamed", id: $new, renamed_from: $id }
+        } ELSE {
+            { result: "skipped" }
+        };
+        RETURN {
+            result: $result.result,
+            table: $table,
+            id: IF $result.id { <string> record::id($result.id) } ELSE { NONE },
+            renamed_from: IF $result.renamed_from {
+                <string> record::id($result.renamed_from)
+            } ELSE {
+                NONE
+            },
+        };
+    "#,
+        )
+        .bind(("table", table))
+        .bind(("record", record))
+        .bind((
+            "conflict",
+            match conflict {
+                Conflict::Skip => "skip",
+                Conflict::Overwrite => "overwrite",
+                Conflict::Rename => "rename",
+            },
+        ))
+        .bind(("new_id", nanoid(8)))
+        .await?
+        .take(3)?;
+    imported.context("failed to import record")
+}
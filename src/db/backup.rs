@@ -0,0 +1,268 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use chrono::Utc;
+use salvo::oapi::ToSchema;
+use serde::Serialize;
+use surrealdb::{Surreal, engine::any::Any};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::{
+    config::BackupConfig,
+    db::archive::{ImportReport, export_archive, read_manifest, replace_archive},
+    storage::DynStorage,
+};
+
+/// 备份目录名的前缀, 后面是UTC时间, 按名称排序就是按时间排序
+const BACKUP_PREFIX: &str = "bulog-";
+
+/// 正在写入的备份, 完成后重命名为正式的名称, 中断时留下的目录会在下次备份时删除
+const TEMP_PREFIX: &str = ".bulog-";
+
+#[derive(Debug, Serialize, ToSchema)]
+#[salvo(schema(name = "BackupInfo"))]
+pub struct BackupInfo {
+    pub name: String,
+    /// unix时间戳, 单位秒
+    pub created_at: u64,
+    /// 每张表备份的记录数
+    pub tables: BTreeMap<String, usize>,
+    pub media: usize,
+}
+
+/// 服务器中共享的备份状态, 同一时间只有一个备份在进行
+#[derive(Clone, Default)]
+pub struct Backups {
+    config: Arc<BackupConfig>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl Backups {
+    pub fn new(config: BackupConfig) -> Self {
+        Backups {
+            config: Arc::new(config),
+            lock: Default::default(),
+        }
+    }
+
+    pub async fn create(
+        &self,
+        db: &Surreal<Any>,
+        storage: &DynStorage,
+    ) -> anyhow::Result<BackupInfo> {
+        let _guard = self.lock.lock().await;
+        let info = create_backup(db, storage, &self.config.dir).await?;
+        let config = self.config.clone();
+        let removed = tokio::task::spawn_blocking(move || prune_backups(&config)).await??;
+        for name in removed {
+            tracing::info!("removed old backup {name}");
+        }
+        Ok(info)
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<BackupInfo>> {
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || list_backups(&config.dir)).await?
+    }
+
+    /// 按配置的间隔定时备份, 没有配置间隔时不启动
+    pub fn schedule(&self, db: Surreal<Any>, storage: DynStorage) -> Option<JoinHandle<()>> {
+        let interval = self.config.interval()?;
+        let backups = self.clone();
+        tracing::info!(
+            "backup to {} every {} hours",
+            self.config.dir.display(),
+            self.config.interval_hours
+        );
+        Some(tokio::spawn(async move {
+            let start = tokio::time::Instant::now() + interval;
+            let mut timer = tokio::time::interval_at(start, interval);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                timer.tick().await;
+                match backups.create(&db, &storage).await {
+                    Ok(info) => tracing::info!("created backup {}", info.name),
+                    Err(err) => tracing::error!("backup failed: {err:#}"),
+                }
+            }
+        }))
+    }
+}
+
+/// 在`dir`中创建一份新的备份, 数据库的记录在同一个事务中读取, 不需要停止服务器
+pub async fn create_backup(
+    db: &Surreal<Any>,
+    storage: &DynStorage,
+    dir: &Path,
+) -> anyhow::Result<BackupInfo> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+            tokio::fs::remove_dir_all(entry.path()).await?;
+        }
+    }
+
+    let name = format!("{BACKUP_PREFIX}{}", Utc::now().format("%Y%m%dT%H%M%S%3fZ"));
+    let temp = dir.join(format!(".{name}"));
+    let manifest = match export_archive(db, storage, &temp).await {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            tokio::fs::remove_dir_all(&temp).await.ok();
+            return Err(err);
+        }
+    };
+    tokio::fs::rename(&temp, dir.join(&name))
+        .await
+        .with_context(|| format!("failed to move backup to {}", dir.join(&name).display()))?;
+    Ok(BackupInfo {
+        name,
+        created_at: manifest.created_at,
+        tables: manifest.tables,
+        media: manifest.media,
+    })
+}
+
+/// 按时间从新到旧排列, 无法读取的目录会被忽略
+pub fn list_backups(dir: &Path) -> anyhow::Result<Vec<BackupInfo>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", dir.display())),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(BACKUP_PREFIX) {
+            continue;
+        }
+        match read_manifest(&entry.path()) {
+            Ok(manifest) => backups.push(BackupInfo {
+                name,
+                created_at: manifest.created_at,
+                tables: manifest.tables,
+                media: manifest.media,
+            }),
+            Err(err) => tracing::warn!("ignore invalid backup {name}: {err:#}"),
+        }
+    }
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// 按保留规则删除旧的备份, 返回删除的备份名称
+pub fn prune_backups(config: &BackupConfig) -> anyhow::Result<Vec<String>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let max_age = config.max_age_days.saturating_mul(24 * 3600);
+    let mut removed = Vec::new();
+    for (index, backup) in list_backups(&config.dir)?.into_iter().enumerate() {
+        if index == 0 {
+            continue;
+        }
+        let too_many = config.keep > 0 && index >= config.keep;
+        let too_old = max_age > 0 && now.saturating_sub(backup.created_at) > max_age;
+        if too_many || too_old {
+            std::fs::remove_dir_all(config.dir.join(&backup.name))?;
+            removed.push(backup.name);
+        }
+    }
+    Ok(removed)
+}
+
+/// `backup`可以是备份目录的路径或者`dir`中的备份名称, 为空时使用最新的备份
+pub fn resolve_backup(dir: &Path, backup: Option<&Path>) -> anyhow::Result<PathBuf> {
+    match backup {
+        Some(path) if path.is_dir() => Ok(path.to_owned()),
+        Some(name) => Ok(dir.join(name)),
+        None => list_backups(dir)?
+            .first()
+            .map(|backup| dir.join(&backup.name))
+            .with_context(|| format!("no backup found in {}", dir.display())),
+    }
+}
+
+/// 用备份替换数据库中的所有记录, 恢复失败时数据库保持不变
+pub async fn restore_backup(
+    db: &Surreal<Any>,
+    storage: &DynStorage,
+    path: &Path,
+) -> anyhow::Result<ImportReport> {
+    replace_archive(db, storage, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::Arc};
+
+    use crate::{
+        config::BackupConfig,
+        db::{
+            model::post::{create_post, delete_post, query_all_posts},
+            test_db,
+        },
+        storage::{DynStorage, MemoryStorage, TempDir},
+    };
+
+    use super::{Backups, list_backups, resolve_backup, restore_backup};
+
+    #[tokio::test]
+    async fn test_backup() -> anyhow::Result<()> {
+        let db = test_db().await?;
+        let storage: DynStorage = Arc::new(MemoryStorage::default());
+        let dir = TempDir::new("bulog-backup");
+        let backups = Backups::new(BackupConfig {
+            dir: dir.to_path_buf(),
+            keep: 2,
+            ..Default::default()
+        });
+
+        let first = create_post(&db, "first".into(), "".into(), false, false).await?;
+        let oldest = backups.create(&db, &storage).await?;
+        create_post(&db, "second".into(), "".into(), false, false).await?;
+        backups.create(&db, &storage).await?;
+        let latest = backups.create(&db, &storage).await?;
+        assert_eq!(latest.tables["post"], 2);
+
+        let listed = backups.list().await?;
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].name, latest.name);
+        assert!(!dir.join(&oldest.name).exists());
+
+        delete_post(&db, first).await?;
+        create_post(&db, "third".into(), "".into(), false, false).await?;
+        let path = resolve_backup(&dir, None)?;
+        let report = restore_backup(&db, &storage, &path).await?;
+        assert!(report.failed.is_empty());
+        let mut titles = query_all_posts(&db)
+            .await?
+            .into_iter()
+            .map(|post| post.title)
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(titles, ["first", "second"]);
+
+        // 有一条记录无法写入时整个恢复失败, 已有的记录保持不变
+        let mut records = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path.join("records.jsonl"))?;
+        writeln!(
+            records,
+            r#"{{"table":"post","record":"{{ id: post:broken, title: 1 }}"}}"#
+        )?;
+        create_post(&db, "fourth".into(), "".into(), false, false).await?;
+        assert!(restore_backup(&db, &storage, &path).await.is_err());
+        assert_eq!(query_all_posts(&db).await?.len(), 3);
+
+        std::fs::remove_dir_all(&dir)?;
+        assert!(list_backups(&dir)?.is_empty());
+        Ok(())
+    }
+}
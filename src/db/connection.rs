@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use surrealdb::{
+    Surreal,
+    engine::any::Any,
+    opt::auth::{Database, Namespace, Record, Root},
+};
+use tokio::task::JoinHandle;
+
+use crate::config::{AuthLevel, DatabaseAuth, DatabaseConfig};
+
+/// 重试连接时的最长间隔
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// 检查远程服务器是否可用的间隔
+const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 远程服务器签发的令牌默认一小时后过期, 在此之前重新登录
+const REAUTH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// 单次健康检查的超时时间
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 需要通过网络连接的数据库, 嵌入式引擎不需要重试和登录
+pub fn is_remote(endpoint: &str) -> bool {
+    ["ws://", "wss://", "http://", "https://"]
+        .iter()
+        .any(|scheme| endpoint.starts_with(scheme))
+}
+
+/// 连接数据库并登录, 远程服务器连接失败时按配置重试
+pub async fn connect(config: &DatabaseConfig) -> anyhow::Result<Surreal<Any>> {
+    let remote = is_remote(&config.endpoint);
+    let mut backoff = Duration::from_secs(1);
+    let mut attempt = 0;
+    let db = loop {
+        match surrealdb::engine::any::connect(config.endpoint.as_str()).await {
+            Ok(db) => break db,
+            Err(err) if remote && attempt < config.connect_retries => {
+                attempt += 1;
+                tracing::warn!(
+                    "failed to connect to {}: {err}, retry {attempt}/{} in {}s",
+                    config.endpoint,
+                    config.connect_retries,
+                    backoff.as_secs()
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    };
+    if let Some(auth) = &config.auth {
+        sign_in(&db, config, auth).await?;
+    }
+    Ok(db)
+}
+
+async fn sign_in(
+    db: &Surreal<Any>,
+    config: &DatabaseConfig,
+    auth: &DatabaseAuth,
+) -> anyhow::Result<()> {
+    #[derive(Serialize)]
+    struct Params<'a> {
+        username: &'a str,
+        password: &'a str,
+    }
+
+    let username = auth.username.as_str();
+    let password = auth.password.as_str();
+    let namespace = config.namespace.as_str();
+    let database = config.database.as_str();
+    let result = match auth.level {
+        AuthLevel::Root => db.signin(Root { username, password }).await,
+        AuthLevel::Namespace => {
+            db.signin(Namespace {
+                namespace,
+                username,
+                password,
+            })
+            .await
+        }
+        AuthLevel::Database => {
+            db.signin(Database {
+                namespace,
+                database,
+                username,
+                password,
+            })
+            .await
+        }
+        AuthLevel::Record => {
+            db.signin(Record {
+                namespace,
+                database,
+                access: auth.access.as_deref().unwrap_or_default(),
+                params: Params { username, password },
+            })
+            .await
+        }
+    };
+    result.map_err(|err| {
+        anyhow::anyhow!(
+            "failed to sign in to {} as {username}: {err}",
+            config.endpoint
+        )
+    })?;
+    Ok(())
+}
+
+/// 数据库能否正常响应, 超时也视为不可用
+pub async fn is_healthy(db: &Surreal<Any>) -> bool {
+    matches!(
+        tokio::time::timeout(HEALTH_TIMEOUT, db.health()).await,
+        Ok(Ok(()))
+    )
+}
+
+/// 定时检查远程服务器, 不可用和恢复时记录日志
+///
+/// websocket连接断开后客户端会自动重连并恢复登录状态, 这里在恢复后和令牌过期前重新登录,
+/// 保证http连接和会话过期的情况也能继续使用
+pub fn monitor(db: Surreal<Any>, config: DatabaseConfig) -> Option<JoinHandle<()>> {
+    if !is_remote(&config.endpoint) {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let mut timer = tokio::time::interval(HEALTH_INTERVAL);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        timer.tick().await;
+        let mut healthy = true;
+        let mut last_auth = tokio::time::Instant::now();
+        loop {
+            timer.tick().await;
+            if !is_healthy(&db).await {
+                if healthy {
+                    tracing::error!("database {} is unreachable", config.endpoint);
+                }
+                healthy = false;
+                continue;
+            }
+            let recovered = !healthy;
+            if recovered {
+                tracing::info!("database {} is reachable again", config.endpoint);
+            }
+            healthy = true;
+            if let Some(auth) = &config.auth
+                && (recovered || last_auth.elapsed() >= REAUTH_INTERVAL)
+            {
+                let result = async {
+                    sign_in(&db, &config, auth).await?;
+                    db.use_ns(&config.namespace)
+                        .use_db(&config.database)
+                        .await?;
+                    anyhow::Ok(())
+                };
+                match result.await {
+                    Ok(()) => last_auth = tokio::time::Instant::now(),
+                    Err(err) => tracing::error!("{err:#}"),
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::DatabaseConfig;
+
+    use super::{connect, is_healthy, is_remote};
+
+    #[tokio::test]
+    async fn test_connect() -> anyhow::Result<()> {
+        assert!(is_remote("wss://db.example:8000"));
+        assert!(!is_remote("rocksdb://./db.rocks"));
+
+        let db = connect(&DatabaseConfig {
+            endpoint: "mem://".to_owned(),
+            ..Default::default()
+        })
+        .await?;
+        assert!(is_healthy(&db).await);
+
+        // 没有监听的端口, 不重试时立即失败
+        let config = DatabaseConfig {
+            endpoint: "ws://127.0.0.1:1".to_owned(),
+            connect_retries: 0,
+            ..Default::default()
+        };
+        assert!(connect(&config).await.is_err());
+        Ok(())
+    }
+}
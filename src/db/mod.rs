@@ -1,25 +1,49 @@
 use cfg_if::cfg_if;
-use model::{
-    Record,
-    config::{ConfigRecord, create_config},
-};
-use surrealdb::{Surreal, engine::any::Any};
+use model::config::{ConfigRecord, create_config, migrate_config};
+use schema::migrate_schema;
+use surrealdb::{Surreal, engine::any::Any, error::Db};
 
+use crate::config::DatabaseConfig;
+
+pub mod archive;
+pub mod backup;
+pub mod connection;
 pub mod model;
+pub mod repo;
+pub mod schema;
 
-fn select_endpoint() -> String {
-    let _default = || {
-        cfg_if! {
-            if #[cfg(feature = "rocksdb_backend")] {
-                "rocksdb://./db.rocks".to_owned()
-            } else if #[cfg(feature = "surrealkv_backend")] {
-                "surrealkv://./db.surreal".to_owned()
-            } else {
-                "mem://".to_owned()
-            }
+/// 根据编译时启用的存储引擎选择默认的数据库地址
+pub fn default_endpoint() -> String {
+    cfg_if! {
+        if #[cfg(feature = "rocksdb_backend")] {
+            "rocksdb://./db.rocks".to_owned()
+        } else if #[cfg(feature = "surrealkv_backend")] {
+            "surrealkv://./db.surreal".to_owned()
+        } else {
+            "mem://".to_owned()
         }
-    };
-    std::env::var("BU_ENDPOINT").ok().unwrap_or_else(_default)
+    }
+}
+
+/// 事务失败时没有执行的语句也会返回错误, 返回真正导致失败的那一个
+pub(crate) fn check_transaction(resp: &mut surrealdb::Response) -> anyhow::Result<()> {
+    let mut errors = resp.take_errors().into_iter().collect::<Vec<_>>();
+    errors.sort_by_key(|(index, _)| *index);
+    let cause = errors.iter().position(|(_, err)| {
+        !matches!(
+            err,
+            surrealdb::Error::Db(
+                Db::QueryNotExecuted | Db::QueryNotExecutedDetail { .. } | Db::QueryCancelled
+            )
+        )
+    });
+    match cause {
+        Some(index) => Err(errors.swap_remove(index).1.into()),
+        None => errors
+            .into_iter()
+            .next()
+            .map_or(Ok(()), |(_, err)| Err(err.into())),
+    }
 }
 
 /// test only
@@ -31,33 +55,47 @@ pub(crate) async fn test_db() -> anyhow::Result<Surreal<Any>> {
     Ok(db)
 }
 
+/// test only, 每个启用的后端各一个未安装的空数据库
+#[allow(unused)]
+#[doc(hidden)]
+pub(crate) async fn test_repos() -> anyhow::Result<Vec<repo::DynRepo>> {
+    #[allow(unused_mut)]
+    let mut repos: Vec<repo::DynRepo> =
+        vec![std::sync::Arc::new(db(Some("mem://".to_owned())).await?)];
+    #[cfg(feature = "sqlite_backend")]
+    repos.push(std::sync::Arc::new(
+        repo::SqliteRepo::open(":memory:").await?,
+    ));
+    Ok(repos)
+}
+
 pub async fn db(specified: Option<String>) -> anyhow::Result<Surreal<Any>> {
-    let endpoint = specified.unwrap_or_else(select_endpoint);
+    open(&DatabaseConfig {
+        endpoint: specified.unwrap_or_else(default_endpoint),
+        ..Default::default()
+    })
+    .await
+}
 
-    if endpoint.starts_with("mem:") {
+/// 连接配置的数据库, 执行迁移后返回
+pub async fn open(config: &DatabaseConfig) -> anyhow::Result<Surreal<Any>> {
+    if config.endpoint.starts_with("mem:") {
         tracing::warn!(
             "You are using an in-memory database, and will lose all data if you stop it!"
         );
     }
 
-    let db = surrealdb::engine::any::connect(endpoint).await?;
-    initialize_db(&db).await?;
+    let db = connection::connect(config).await?;
+    initialize_db(&db, config).await?;
+    migrate_config(&db).await?;
     Ok(db)
 }
 
-async fn initialize_db(db: &Surreal<Any>) -> anyhow::Result<()> {
+async fn initialize_db(db: &Surreal<Any>, config: &DatabaseConfig) -> anyhow::Result<()> {
     tracing::info!("Initializing database");
-    db.use_ns("bulog").use_db("blog").await?;
-    /* let blog_config: Option<ConfigRecord> = db.select(("config", "bulog")).await?;
-    // 如果config表是空的, 那么认定博客程序未初始化
-    // 在config表中插入一条`唯一`的记录, 用于存放博客全局配置
-    // 记录id硬编码为: bulog
-    if blog_config.is_none() {
-        tracing::info!("first start, initializing blog config");
-        let _: Option<Record> = db
-            .create(("config", "bulog"))
-            .content(ConfigRecord::default())
-            .await?;
-    } */
+    db.use_ns(&config.namespace)
+        .use_db(&config.database)
+        .await?;
+    migrate_schema(db).await?;
     Ok(())
 }
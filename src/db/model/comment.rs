@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use surrealdb::{Surreal, engine::any::Any};
+
+use super::deserialize_record_id;
+use crate::nano_id::nanoid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentRecord {
+    #[serde(deserialize_with = "deserialize_record_id")]
+    pub id: SmolStr,
+    /// 所属文章的id
+    pub post: SmolStr,
+    /// 回复的评论id, 顶层评论为空
+    pub parent: Option<SmolStr>,
+    pub author: SmolStr,
+    pub email: SmolStr,
+    pub url: SmolStr,
+    pub content: SmolStr,
+    pub created_time: surrealdb::Datetime,
+    /// 未通过审核的评论不会公开显示
+    pub approved: bool,
+}
+
+/// `created_time`为空时使用当前时间
+#[derive(Debug, Serialize)]
+pub struct NewComment {
+    pub post: SmolStr,
+    pub parent: Option<SmolStr>,
+    pub author: SmolStr,
+    pub email: SmolStr,
+    pub url: SmolStr,
+    pub content: SmolStr,
+    pub created_time: Option<surrealdb::Datetime>,
+    pub approved: bool,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn create_comment(db: &Surreal<Any>, comment: NewComment) -> anyhow::Result<SmolStr> {
+    let id = nanoid(8);
+    db.query(
+        r#"
+        CREATE type::thing("comment", $id) SET
+            post = $comment.post,
+            parent = $comment.parent,
+            author = $comment.author,
+            email = $comment.email,
+            url = $comment.url,
+            content = $comment.content,
+            created_time = $comment.created_time ?? time::now(),
+            approved = $comment.approved;
+    "#,
+    )
+    .bind(("id", id.clone()))
+    .bind(("comment", comment))
+    .await?
+    .check()?;
+    Ok(id)
+}
+
+#[tracing::instrument(skip_all, fields(%post))]
+pub async fn query_comments_by_post(
+    db: &Surreal<Any>,
+    post: SmolStr,
+) -> anyhow::Result<Vec<CommentRecord>> {
+    let mut resp = db
+        .query("SELECT * FROM comment WHERE post = $post ORDER BY created_time ASC")
+        .bind(("post", post))
+        .await?;
+    let comments: Vec<CommentRecord> = resp.take(0)?;
+    Ok(comments)
+}
@@ -1,17 +1,51 @@
 use std::convert::identity;
 
+use salvo::oapi::ToSchema;
 use serde::{Deserialize, Serialize};
-use surrealdb::{Surreal, engine::any::Any};
+use surrealdb::{Surreal, engine::any::Any, error::Db};
 
-use super::Record;
+use crate::db::check_transaction;
 
-#[derive(Debug, Serialize, Deserialize, bulog_derive::Optional)]
+/// 当前站点设置的版本, 每新增一个迁移就加一
+///
+/// 和[`SCHEMA_VERSION`](crate::db::schema::SCHEMA_VERSION)分开计数: 版本号保存在设置记录里,
+/// 会随归档一起导出导入, 导入旧归档后据此补全设置; SQLite后端没有数据库结构迁移, 也用它升级设置
+pub const SETTINGS_VERSION: usize = MIGRATIONS.len();
+
+/// 站点设置的迁移脚本, 第`n`个脚本把设置从版本`n`升级到`n + 1`,
+/// 新增字段时在这里追加一条用`$defaults`补全旧数据的语句, 不要修改已有的脚本
+const MIGRATIONS: &[&str] = &[
+    // 1: 语言, 时区, 分页, 页脚, 社交链接, 评论策略和订阅选项
+    "UPDATE config:bulog SET \
+        language = language ?? $defaults.language, \
+        timezone = timezone ?? $defaults.timezone, \
+        posts_per_page = posts_per_page ?? $defaults.posts_per_page, \
+        footer_html = footer_html ?? $defaults.footer_html, \
+        social_links = social_links ?? $defaults.social_links, \
+        comment_policy = comment_policy ?? $defaults.comment_policy, \
+        feed = feed ?? $defaults.feed;",
+];
+
+/// 缺失的字段使用默认值, 读取旧版本的数据时不会失败
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, bulog_derive::Optional)]
+#[serde(default)]
+#[salvo(schema(name = "ConfigRecord"))]
+#[optional(derive(ToSchema), salvo(schema(name = "ConfigRecordOption")))]
 pub struct ConfigRecord {
     pub title: String,
     pub description: String,
     /// 确保无法获取到password
     #[serde(skip_deserializing)]
     pub password: String,
+    /// BCP 47 语言标签, 例如`zh-CN`
+    pub language: String,
+    /// IANA 时区名或`UTC`
+    pub timezone: String,
+    pub posts_per_page: u32,
+    pub footer_html: String,
+    pub social_links: Vec<SocialLink>,
+    pub comment_policy: CommentPolicy,
+    pub feed: FeedOptions,
 }
 
 impl Default for ConfigRecord {
@@ -20,17 +54,214 @@ impl Default for ConfigRecord {
             title: "bulog".to_owned(),
             description: "A sample blog program".to_owned(),
             password: "".to_owned(),
+            language: "en".to_owned(),
+            timezone: "UTC".to_owned(),
+            posts_per_page: 10,
+            footer_html: "".to_owned(),
+            social_links: Vec::new(),
+            comment_policy: CommentPolicy::default(),
+            feed: FeedOptions::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[salvo(schema(name = "SocialLink"))]
+pub struct SocialLink {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[salvo(schema(name = "CommentPolicy"))]
+pub enum CommentPolicy {
+    Open,
+    #[default]
+    Moderated,
+    Closed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+#[salvo(schema(name = "FeedOptions"))]
+pub struct FeedOptions {
+    pub enabled: bool,
+    /// 订阅中包含的文章数量
+    pub items: u32,
+    /// 输出全文而不是摘要
+    pub full_content: bool,
+}
+
+impl Default for FeedOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            items: 20,
+            full_content: false,
+        }
+    }
+}
+
+/// 检查待更新的配置字段, 返回不合法的原因
+pub fn validate_config(config: &ConfigRecordOption) -> Result<(), &'static str> {
+    if let Some(title) = &config.title {
+        if title.trim().is_empty() {
+            return Err("title cannot be empty");
+        }
+        if title.chars().count() > 128 {
+            return Err("title is too long");
+        }
+    }
+    if config
+        .description
+        .as_ref()
+        .is_some_and(|desc| desc.chars().count() > 1024)
+    {
+        return Err("description is too long");
+    }
+    if let Some(pwd) = &config.password {
+        if pwd.is_empty() {
+            return Err("password cannot be empty");
+        }
+        if pwd.len() > 256 {
+            return Err("password is too long");
         }
     }
+    if config.language.as_ref().is_some_and(|lang| {
+        lang.is_empty()
+            || lang.len() > 35
+            || !lang.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }) {
+        return Err("invalid language tag");
+    }
+    if config.timezone.as_ref().is_some_and(|tz| {
+        tz.is_empty()
+            || tz.len() > 64
+            || !tz
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "/_+-:".contains(c))
+    }) {
+        return Err("invalid timezone");
+    }
+    if config
+        .posts_per_page
+        .is_some_and(|n| !(1..=100).contains(&n))
+    {
+        return Err("posts_per_page must be between 1 and 100");
+    }
+    if config
+        .footer_html
+        .as_ref()
+        .is_some_and(|html| html.len() > 16 * 1024)
+    {
+        return Err("footer_html is too long");
+    }
+    if let Some(links) = &config.social_links {
+        if links.len() > 32 {
+            return Err("too many social links");
+        }
+        for link in links {
+            if link.name.trim().is_empty() {
+                return Err("social link name cannot be empty");
+            }
+            if !["http://", "https://", "mailto:"]
+                .iter()
+                .any(|scheme| link.url.starts_with(scheme))
+            {
+                return Err("social link url must be http, https or mailto");
+            }
+        }
+    }
+    if config
+        .feed
+        .as_ref()
+        .is_some_and(|feed| !(1..=100).contains(&feed.items))
+    {
+        return Err("feed items must be between 1 and 100");
+    }
+    Ok(())
+}
+
+/// 已经安装过时再次安装返回的错误
+#[derive(Debug)]
+pub struct RepeatInstallation;
+
+impl std::fmt::Display for RepeatInstallation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("repeat installation")
+    }
 }
 
+impl std::error::Error for RepeatInstallation {}
+
+/// 在同一个事务中写入配置和密码, 已存在配置时报错, 保证并发安装只有一个能成功
+#[tracing::instrument(skip_all)]
 pub async fn create_config(db: &Surreal<Any>, mut config: ConfigRecord) -> anyhow::Result<()> {
     let pwd = std::mem::take(&mut config.password);
-    let _: Option<Record> = db.create(("config", "bulog")).content(config).await?;
-    update_password(db, pwd).await?;
+    let mut resp = db
+        .query(
+            r#"
+        BEGIN TRANSACTION;
+
+        IF record::exists(config:bulog) {
+            THROW "repeat installation";
+        };
+        CREATE config:bulog CONTENT $config;
+        UPDATE config:bulog SET
+            password = crypto::argon2::generate($pwd),
+            version = $version;
+
+        COMMIT TRANSACTION;
+    "#,
+        )
+        .bind(("config", config))
+        .bind(("pwd", pwd))
+        .bind(("version", SETTINGS_VERSION))
+        .await?;
+    check_transaction(&mut resp).map_err(|err| match err.downcast_ref() {
+        Some(surrealdb::Error::Db(Db::Thrown(message))) if message == "repeat installation" => {
+            RepeatInstallation.into()
+        }
+        _ => err,
+    })
+}
+
+/// 把已安装博客的设置升级到当前版本, 遇到更新版本程序写入的设置时拒绝启动
+#[tracing::instrument(skip_all)]
+pub async fn migrate_config(db: &Surreal<Any>) -> anyhow::Result<()> {
+    if is_new_install(db).await? {
+        return Ok(());
+    }
+    let version = db
+        .query("RETURN (SELECT version FROM ONLY config:bulog).version")
+        .await?
+        .take::<Option<usize>>(0)?
+        .unwrap_or_default();
+    if version > SETTINGS_VERSION {
+        anyhow::bail!(
+            "site settings version {version} is newer than supported version {SETTINGS_VERSION}"
+        );
+    }
+
+    for (from, script) in MIGRATIONS.iter().enumerate().skip(version) {
+        tracing::info!(
+            "migrating site settings from version {from} to {}",
+            from + 1
+        );
+        db.query("BEGIN TRANSACTION;")
+            .query(*script)
+            .query("UPDATE config:bulog SET version = $version;")
+            .query("COMMIT TRANSACTION;")
+            .bind(("defaults", ConfigRecord::default()))
+            .bind(("version", from + 1))
+            .await?
+            .check()?;
+    }
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn query_config(db: &Surreal<Any>) -> anyhow::Result<ConfigRecord> {
     db.select(("config", "bulog"))
         .await
@@ -39,6 +270,7 @@ pub async fn query_config(db: &Surreal<Any>) -> anyhow::Result<ConfigRecord> {
         .and_then(identity)
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn update_config(
     db: &Surreal<Any>,
     mut config: ConfigRecordOption,
@@ -54,6 +286,7 @@ pub async fn update_config(
         .map(|_: Option<ConfigRecord>| ())
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn update_password(db: &Surreal<Any>, pwd: String) -> anyhow::Result<()> {
     db.query("UPDATE config:bulog SET password = crypto::argon2::generate($pwd); \
     IF !crypto::argon2::compare((SELECT password FROM ONLY config:bulog).password, $pwd) { THROW \"internal error: passwords not equal\"; };")
@@ -63,17 +296,19 @@ pub async fn update_password(db: &Surreal<Any>, pwd: String) -> anyhow::Result<(
         .map(|_| ())
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn verify_password(db: &Surreal<Any>, pwd: String) -> anyhow::Result<bool> {
     db.query(
         "RETURN crypto::argon2::compare((SELECT password FROM ONLY config:bulog).password, $pwd)",
     )
     .bind(("pwd", pwd))
     .await
-    .and_then(|mut res| res.take::<Option<_>>(0))
+    .map_err(anyhow::Error::from)
+    .and_then(|mut res| res.take::<Option<_>>(0).map_err(Into::into))
     .map(|opt| opt.unwrap_or_default())
-    .map_err(Into::into)
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn is_new_install(db: &Surreal<Any>) -> anyhow::Result<bool> {
     db.select(("config", "bulog"))
         .await
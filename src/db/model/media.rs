@@ -0,0 +1,156 @@
+use mime_guess::mime::{self, Mime};
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use smol_str::{SmolStr, ToSmolStr};
+use surrealdb::{Surreal, engine::any::Any};
+
+use super::deserialize_record_id;
+use crate::{
+    db::{check_transaction, repo::Repository},
+    nano_id::nanoid,
+    storage::DynStorage,
+};
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[salvo(schema(name = "MediaRecord"))]
+pub struct MediaRecord {
+    #[serde(deserialize_with = "deserialize_record_id")]
+    #[salvo(schema(value_type = String))]
+    pub id: SmolStr,
+    /// 上传时的原始文件名
+    #[salvo(schema(value_type = String))]
+    pub filename: SmolStr,
+    #[salvo(schema(value_type = String))]
+    pub mime: SmolStr,
+    pub size: u64,
+    /// 只有图片才有尺寸
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// 内容的sha256, 同时也是文件在存储后端中的key
+    #[salvo(schema(value_type = String))]
+    pub hash: SmolStr,
+    #[salvo(schema(value_type = String, format = DateTime))]
+    pub created_time: surrealdb::Datetime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NewMedia {
+    pub filename: SmolStr,
+    pub mime: SmolStr,
+    pub size: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub hash: SmolStr,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreatedMedia {
+    created: bool,
+    media: MediaRecord,
+}
+
+/// 相同内容的文件只保存一份, 返回的`bool`表示是否新建了记录
+#[tracing::instrument(skip_all)]
+pub async fn create_media(
+    db: &Surreal<Any>,
+    media: NewMedia,
+) -> anyhow::Result<(MediaRecord, bool)> {
+    let mut resp = db
+        .query(
+            r#"
+        BEGIN TRANSACTION;
+
+        LET $existing = (SELECT * FROM ONLY media WHERE hash = $media.hash LIMIT 1);
+        IF $existing {
+            RETURN { created: false, media: $existing };
+        } ELSE {
+            RETURN {
+                created: true,
+                media: (CREATE ONLY type::thing("media", $id) SET
+                    filename = $media.filename,
+                    mime = $media.mime,
+                    size = $media.size,
+                    width = $media.width,
+                    height = $media.height,
+                    hash = $media.hash,
+                    created_time = time::now())
+            };
+        };
+
+        COMMIT TRANSACTION;
+    "#,
+        )
+        .bind(("id", nanoid(8)))
+        .bind(("media", media))
+        .await?;
+    check_transaction(&mut resp)?;
+    let created: Option<CreatedMedia> = resp.take(0)?;
+    created
+        .map(|created| (created.media, created.created))
+        .ok_or_else(|| anyhow::anyhow!("failed to create media"))
+}
+
+/// 计算哈希并写入存储后端后创建记录, 图片会读取尺寸
+#[tracing::instrument(skip_all, fields(%filename, size = data.len()))]
+pub async fn store_media(
+    repo: &dyn Repository,
+    storage: &DynStorage,
+    filename: SmolStr,
+    mime: Mime,
+    data: Vec<u8>,
+) -> anyhow::Result<(MediaRecord, bool)> {
+    let hash = format!("{:x}", Sha256::digest(&data)).to_smolstr();
+    let (width, height) = if mime.type_() == mime::IMAGE {
+        imagesize::blob_size(&data)
+            .map(|size| (Some(size.width as u32), Some(size.height as u32)))
+            .unwrap_or_default()
+    } else {
+        (None, None)
+    };
+
+    let size = data.len() as u64;
+    if !storage.exists(&hash).await? {
+        storage.put(&hash, data).await?;
+    }
+    repo.create_media(NewMedia {
+        filename,
+        mime: mime.essence_str().to_smolstr(),
+        size,
+        width,
+        height,
+        hash,
+    })
+    .await
+}
+
+/// 媒体文件的访问路径
+pub fn file_url(id: &str) -> String {
+    format!("/v1/media/{id}/file")
+}
+
+#[tracing::instrument(skip_all, fields(%id))]
+pub async fn query_media(db: &Surreal<Any>, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+    db.select(("media", &*id)).await.map_err(Into::into)
+}
+
+#[tracing::instrument(skip_all, fields(page = page, page_size = page_size))]
+pub async fn query_media_by_page(
+    db: &Surreal<Any>,
+    page: usize,
+    page_size: usize,
+) -> anyhow::Result<Vec<MediaRecord>> {
+    let mut resp = db
+        .query("SELECT * FROM media ORDER BY created_time DESC LIMIT $limit START $start;")
+        .bind(("limit", page_size))
+        .bind(("start", page * page_size))
+        .await?;
+    let media: Vec<MediaRecord> = resp.take(0)?;
+    Ok(media)
+}
+
+/// 返回被删除的记录, 调用者负责删除存储后端中的文件
+#[tracing::instrument(skip_all, fields(%id))]
+pub async fn delete_media(db: &Surreal<Any>, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+    db.delete(("media", &*id)).await.map_err(Into::into)
+}
@@ -1,132 +1,360 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use smol_str::{SmolStr, ToSmolStr};
 use surrealdb::RecordId;
 
+pub mod comment;
 pub mod config;
+pub mod media;
+// 文章相关接口还没有接入路由
+#[allow(dead_code)]
 pub mod post;
+pub mod session;
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct Record {
     pub id: RecordId,
 }
 
+/// 只保留记录id中的key部分
+pub(crate) fn deserialize_record_id<'de, D>(deserializer: D) -> Result<SmolStr, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let record_id = RecordId::deserialize(deserializer)?;
+    Ok(record_id.key().to_smolstr())
+}
+
 #[cfg(test)]
 mod tests {
     use smol_str::format_smolstr;
 
-    use crate::db::model::{
-        config::{
-            ConfigRecordOption, is_new_install, query_config, update_config, verify_password,
-        },
-        post::{
-            PostRecord, PostRecordOption, create_post, query_post, query_posts_by_page, update_post,
+    use crate::db::{
+        model::{
+            comment::NewComment,
+            config::{
+                CommentPolicy, ConfigRecord, ConfigRecordOption, SETTINGS_VERSION, SocialLink,
+                migrate_config, query_config, validate_config,
+            },
+            media::NewMedia,
+            post::{NewPost, PostRecordOption},
         },
+        repo::DynRepo,
+        test_repos,
     };
 
-    #[tokio::test]
-    async fn test_db_config() -> anyhow::Result<()> {
-        let db = crate::db::test_db().await?;
-        assert!(!is_new_install(&db).await?);
+    // 除了直接读写SurrealQL的测试, 每个测试都在所有启用的后端上运行
 
-        let conf = query_config(&db).await?;
-        assert_eq!(conf.title, "bulog");
+    /// 已经用默认设置安装过的数据库
+    async fn installed_repos() -> anyhow::Result<Vec<DynRepo>> {
+        let repos = test_repos().await?;
+        for repo in &repos {
+            repo.create_config(ConfigRecord::default()).await?;
+        }
+        Ok(repos)
+    }
 
-        update_config(&db, ConfigRecordOption {
-            description: Some("updated".to_owned()),
+    async fn create_post(repo: &DynRepo, title: &str) -> anyhow::Result<smol_str::SmolStr> {
+        repo.insert_post(NewPost {
+            title: title.into(),
+            content: "test content".into(),
             ..Default::default()
         })
-        .await?;
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_db_config() -> anyhow::Result<()> {
+        for repo in installed_repos().await? {
+            assert!(!repo.is_new_install().await?);
+
+            let conf = repo.query_config().await?;
+            assert_eq!(conf.title, "bulog");
+
+            repo.update_config(ConfigRecordOption {
+                description: Some("updated".to_owned()),
+                ..Default::default()
+            })
+            .await?;
+
+            let conf = repo.query_config().await?;
+            assert_eq!(conf.title, "bulog");
+            assert_eq!(conf.description, "updated");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_install() -> anyhow::Result<()> {
+        for repo in test_repos().await? {
+            assert!(repo.is_new_install().await?);
+
+            let (a, b) = tokio::join!(
+                repo.create_config(ConfigRecord::default()),
+                repo.create_config(ConfigRecord::default())
+            );
+            assert!(a.is_ok() ^ b.is_ok());
+            assert!(!repo.is_new_install().await?);
+            assert!(repo.create_config(ConfigRecord::default()).await.is_err());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_settings() -> anyhow::Result<()> {
+        for repo in installed_repos().await? {
+            let conf = repo.query_config().await?;
+            assert_eq!(conf.posts_per_page, 10);
+            assert_eq!(conf.comment_policy, CommentPolicy::Moderated);
+
+            let patch = ConfigRecordOption {
+                comment_policy: Some(CommentPolicy::Closed),
+                social_links: Some(vec![SocialLink {
+                    name: "github".to_owned(),
+                    url: "https://github.com/juzi5201314/bulog".to_owned(),
+                }]),
+                ..Default::default()
+            };
+            assert!(validate_config(&patch).is_ok());
+            repo.update_config(patch).await?;
+
+            let conf = repo.query_config().await?;
+            assert_eq!(conf.comment_policy, CommentPolicy::Closed);
+            assert_eq!(conf.social_links[0].name, "github");
+            assert!(conf.feed.enabled);
+        }
+
+        assert!(
+            validate_config(&ConfigRecordOption {
+                posts_per_page: Some(0),
+                ..Default::default()
+            })
+            .is_err()
+        );
+        assert!(
+            validate_config(&ConfigRecordOption {
+                social_links: Some(vec![SocialLink {
+                    name: "x".to_owned(),
+                    url: "javascript:alert(1)".to_owned(),
+                }]),
+                ..Default::default()
+            })
+            .is_err()
+        );
+
+        Ok(())
+    }
 
+    #[tokio::test]
+    async fn test_settings_migration() -> anyhow::Result<()> {
+        let db = crate::db::db(Some("mem://".to_owned())).await?;
+        db.query("CREATE config:bulog SET title = 'old', description = 'old blog'")
+            .await?
+            .check()?;
+
+        migrate_config(&db).await?;
         let conf = query_config(&db).await?;
-        assert_eq!(conf.title, "bulog");
-        assert_eq!(conf.description, "updated");
+        assert_eq!(conf.title, "old");
+        assert_eq!(conf.timezone, "UTC");
+        let version: Option<usize> = db
+            .query("RETURN (SELECT version FROM ONLY config:bulog).version")
+            .await?
+            .take(0)?;
+        assert_eq!(version, Some(SETTINGS_VERSION));
+        let raw: Option<String> = db
+            .query("RETURN (SELECT comment_policy FROM ONLY config:bulog).comment_policy")
+            .await?
+            .take(0)?;
+        assert_eq!(raw.as_deref(), Some("moderated"));
+
+        db.query("UPDATE config:bulog SET version = $v")
+            .bind(("v", SETTINGS_VERSION + 1))
+            .await?
+            .check()?;
+        assert!(migrate_config(&db).await.is_err());
 
         Ok(())
     }
 
     #[tokio::test]
     async fn test_password() -> anyhow::Result<()> {
-        let db = crate::db::test_db().await?;
-        let conf = query_config(&db).await?;
-        assert!(conf.password.is_empty());
-        update_config(&db, ConfigRecordOption {
-            title: Some("new title".to_owned()),
-            password: Some("pwd1".to_owned()),
-            ..Default::default()
-        })
-        .await?;
-        let conf = query_config(&db).await?;
-        assert_eq!(conf.title, "new title");
-        assert!(conf.password.is_empty());
-        assert!(verify_password(&db, "pwd1".to_owned()).await?);
-        assert!(!verify_password(&db, "pwd2".to_owned()).await?);
+        for repo in installed_repos().await? {
+            let conf = repo.query_config().await?;
+            assert!(conf.password.is_empty());
+            repo.update_config(ConfigRecordOption {
+                title: Some("new title".to_owned()),
+                password: Some("pwd1".to_owned()),
+                ..Default::default()
+            })
+            .await?;
+            let conf = repo.query_config().await?;
+            assert_eq!(conf.title, "new title");
+            assert!(conf.password.is_empty());
+            assert!(repo.verify_password("pwd1".to_owned()).await?);
+            assert!(!repo.verify_password("pwd2".to_owned()).await?);
+        }
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_create_post() -> anyhow::Result<()> {
-        let db = crate::db::test_db().await?;
+    async fn test_session() -> anyhow::Result<()> {
+        for repo in installed_repos().await? {
+            let secret = repo.session_secret().await?;
+            assert_eq!(secret.len(), 64);
+            assert_eq!(repo.session_secret().await?, secret);
 
-        let id = create_post(
-            &db,
-            "test title".into(),
-            "test content".into(),
-            false,
-            false,
-        )
-        .await?;
+            assert_eq!(repo.query_session_epoch().await?, 0);
+            assert_eq!(repo.bump_session_epoch().await?, 1);
+            assert_eq!(repo.bump_session_epoch().await?, 2);
+            assert_eq!(repo.query_session_epoch().await?, 2);
+        }
+        Ok(())
+    }
 
-        let posts: Vec<PostRecord> = db.query("select * from post").await?.take(0)?;
+    #[tokio::test]
+    async fn test_create_post() -> anyhow::Result<()> {
+        for repo in installed_repos().await? {
+            let id = create_post(&repo, "test title").await?;
 
-        assert_eq!(posts[0].id, id);
-        assert_eq!(posts[0].title, "test title");
-        assert_eq!(posts[0].content, "test content");
+            let posts = repo.query_all_posts().await?;
 
+            assert_eq!(posts[0].id, id);
+            assert_eq!(posts[0].title, "test title");
+            assert_eq!(posts[0].content, "test content");
+            assert!(posts[0].tags.is_empty() && posts[0].slug.is_none());
+
+            let created_time = surrealdb::Datetime::from(
+                chrono::DateTime::parse_from_rfc3339("2020-01-02T03:04:05.123Z")?
+                    .with_timezone(&chrono::Utc),
+            );
+            let id = repo
+                .insert_post(NewPost {
+                    title: "imported".into(),
+                    created_time: Some(created_time.clone()),
+                    draft: true,
+                    tags: vec!["rust".into()],
+                    slug: Some("imported".into()),
+                    ..Default::default()
+                })
+                .await?;
+            let post = repo.query_post_by_slug("imported".into()).await?.unwrap();
+            assert_eq!(post.id, id);
+            assert_eq!(post.created_time, created_time);
+            assert!(post.draft && !post.pinned);
+            assert_eq!(post.tags, ["rust"]);
+            assert!(repo.query_post_by_slug("missing".into()).await?.is_none());
+        }
         Ok(())
     }
 
     #[tokio::test]
     async fn test_update_post() -> anyhow::Result<()> {
-        let db = crate::db::test_db().await?;
-        let id = create_post(
-            &db,
-            "test title".into(),
-            "test content".into(),
-            false,
-            false,
-        )
-        .await?;
-        update_post(&db, id.clone(), PostRecordOption {
-            title: Some("new title".into()),
-            ..Default::default()
-        })
-        .await?;
-        let post = query_post(&db, id).await?.unwrap();
-        assert_eq!(post.title, "new title");
+        for repo in installed_repos().await? {
+            let id = create_post(&repo, "test title").await?;
+            repo.update_post(
+                id.clone(),
+                PostRecordOption {
+                    title: Some("new title".into()),
+                    pinned: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            let post = repo.query_post(id.clone()).await?.unwrap();
+            assert_eq!(post.title, "new title");
+            assert_eq!(post.content, "test content");
+            assert!(post.pinned);
+
+            repo.delete_post(id.clone()).await?;
+            assert!(repo.query_post(id).await?.is_none());
+        }
         Ok(())
     }
 
     #[tokio::test]
     async fn test_query_post() -> anyhow::Result<()> {
-        let db = crate::db::test_db().await?;
-        for i in 0..101 {
-            create_post(&db, format_smolstr!("post {i}"), "".into(), false, false).await?;
+        for repo in installed_repos().await? {
+            for i in 0..101 {
+                create_post(&repo, &format_smolstr!("post {i}")).await?;
+            }
+            let posts = repo.query_posts_by_page(0, 10, false).await?;
+            assert_eq!(
+                posts.first().map(|post| post.title.clone()),
+                Some("post 100".into())
+            );
+            assert_eq!(
+                posts.get(9).map(|post| post.title.clone()),
+                Some("post 91".into())
+            );
+            assert_eq!(posts.len(), 10);
+            let posts = repo.query_posts_by_page(10, 10, false).await?;
+            assert_eq!(posts.len(), 1);
+            assert_eq!(
+                posts.first().map(|post| post.title.clone()),
+                Some("post 0".into())
+            );
+            let posts = repo.query_posts_by_page(0, 1, true).await?;
+            assert_eq!(posts[0].title, "post 0");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_media() -> anyhow::Result<()> {
+        for repo in installed_repos().await? {
+            let media = || NewMedia {
+                filename: "a.png".into(),
+                mime: "image/png".into(),
+                size: 3,
+                width: Some(1),
+                height: Some(2),
+                hash: "abc".into(),
+            };
+            let (created, is_new) = repo.create_media(media()).await?;
+            assert!(is_new);
+            assert_eq!(created.width, Some(1));
+            let (existing, is_new) = repo.create_media(media()).await?;
+            assert!(!is_new);
+            assert_eq!(existing.id, created.id);
+
+            assert_eq!(repo.query_media_by_page(0, 10).await?.len(), 1);
+            let found = repo.query_media(created.id.clone()).await?.unwrap();
+            assert_eq!(found.hash, "abc");
+            let deleted = repo.delete_media(created.id.clone()).await?.unwrap();
+            assert_eq!(deleted.id, created.id);
+            assert!(repo.delete_media(created.id).await?.is_none());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_comments() -> anyhow::Result<()> {
+        for repo in installed_repos().await? {
+            let post = create_post(&repo, "commented").await?;
+            let comment = |content: &str, parent| NewComment {
+                post: post.clone(),
+                parent,
+                author: "reader".into(),
+                email: "".into(),
+                url: "".into(),
+                content: content.into(),
+                created_time: None,
+                approved: true,
+            };
+            let first = repo.create_comment(comment("first", None)).await?;
+            repo.create_comment(comment("reply", Some(first.clone())))
+                .await?;
+
+            let comments = repo.query_comments_by_post(post).await?;
+            assert_eq!(comments.len(), 2);
+            assert_eq!(comments[0].id, first);
+            assert_eq!(comments[1].parent.as_ref(), Some(&first));
+            assert!(
+                repo.query_comments_by_post("other".into())
+                    .await?
+                    .is_empty()
+            );
         }
-        let posts = query_posts_by_page(&db, 0, 10, false).await?;
-        assert_eq!(
-            posts.get(0).map(|post| post.title.clone()),
-            Some("post 100".into())
-        );
-        assert_eq!(
-            posts.get(9).map(|post| post.title.clone()),
-            Some("post 91".into())
-        );
-        assert_eq!(posts.len(), 10);
-        let posts = query_posts_by_page(&db, 10, 10, false).await?;
-        assert_eq!(posts.len(), 1);
-        assert_eq!(
-            posts.get(0).map(|post| post.title.clone()),
-            Some("post 0".into())
-        );
         Ok(())
     }
 }
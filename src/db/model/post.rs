@@ -1,10 +1,11 @@
-use serde::{Deserialize, Deserializer, Serialize};
-use smol_str::{SmolStr, ToSmolStr};
-use surrealdb::{RecordId, Surreal, engine::any::Any};
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use surrealdb::{Surreal, engine::any::Any};
 
+use super::deserialize_record_id;
 use crate::nano_id::nanoid;
 
-#[derive(Debug, Serialize, Deserialize, bulog_derive::Optional)]
+#[derive(Debug, Clone, Serialize, Deserialize, bulog_derive::Optional)]
 pub struct PostRecord {
     pub title: SmolStr,
     pub content: SmolStr,
@@ -13,16 +14,26 @@ pub struct PostRecord {
     pub id: SmolStr,
     pub draft: bool,
     pub pinned: bool,
+    #[serde(default)]
+    pub tags: Vec<SmolStr>,
+    /// 从其他博客导入时保留的原始链接名
+    #[serde(default)]
+    pub slug: Option<SmolStr>,
 }
 
-fn deserialize_record_id<'de, D>(deserializer: D) -> Result<SmolStr, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let record_id = RecordId::deserialize(deserializer)?;
-    Ok(record_id.key().to_smolstr())
+/// 创建文章需要的字段, `created_time`为空时使用当前时间
+#[derive(Debug, Clone, Default)]
+pub struct NewPost {
+    pub title: SmolStr,
+    pub content: SmolStr,
+    pub created_time: Option<surrealdb::Datetime>,
+    pub draft: bool,
+    pub pinned: bool,
+    pub tags: Vec<SmolStr>,
+    pub slug: Option<SmolStr>,
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn create_post(
     db: &Surreal<Any>,
     title: SmolStr,
@@ -30,6 +41,21 @@ pub async fn create_post(
     draft: bool,
     pinned: bool,
 ) -> anyhow::Result<SmolStr> {
+    insert_post(
+        db,
+        NewPost {
+            title,
+            content,
+            draft,
+            pinned,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_post(db: &Surreal<Any>, post: NewPost) -> anyhow::Result<SmolStr> {
     loop {
         let id = nanoid(6);
         let mut resp = db
@@ -43,11 +69,13 @@ pub async fn create_post(
             CREATE
                 type::thing("post",$id)
             SET 
-                created_time = time::now(), 
+                created_time = $created_time ?? time::now(), 
                 title = $title, 
                 content = $content, 
                 draft = $draft, 
-                pinned = $pinned;
+                pinned = $pinned,
+                tags = $tags,
+                slug = $slug;
             RETURN 1;
         };
 
@@ -55,10 +83,13 @@ pub async fn create_post(
     "#,
             )
             .bind(("id", id.clone()))
-            .bind(("title", title.clone()))
-            .bind(("content", content.clone()))
-            .bind(("draft", draft))
-            .bind(("pinned", pinned))
+            .bind(("created_time", post.created_time.clone()))
+            .bind(("title", post.title.clone()))
+            .bind(("content", post.content.clone()))
+            .bind(("draft", post.draft))
+            .bind(("pinned", post.pinned))
+            .bind(("tags", post.tags.clone()))
+            .bind(("slug", post.slug.clone()))
             .await?;
         let is_ok: Option<usize> = resp.take(0)?;
 
@@ -68,14 +99,30 @@ pub async fn create_post(
     }
 }
 
+#[tracing::instrument(skip_all, fields(%id))]
 pub async fn query_post(db: &Surreal<Any>, id: SmolStr) -> anyhow::Result<Option<PostRecord>> {
     db.select(("post", &*id)).await.map_err(Into::into)
 }
 
+#[tracing::instrument(skip_all, fields(%slug))]
+pub async fn query_post_by_slug(
+    db: &Surreal<Any>,
+    slug: SmolStr,
+) -> anyhow::Result<Option<PostRecord>> {
+    let mut resp = db
+        .query("SELECT * FROM post WHERE slug = $slug LIMIT 1")
+        .bind(("slug", slug))
+        .await?;
+    let post: Option<PostRecord> = resp.take(0)?;
+    Ok(post)
+}
+
+#[tracing::instrument(skip_all)]
 pub async fn query_all_posts(db: &Surreal<Any>) -> anyhow::Result<Vec<PostRecord>> {
     db.select("post").await.map_err(Into::into)
 }
 
+#[tracing::instrument(skip_all, fields(page = page, page_size = page_size))]
 pub async fn query_posts_by_page(
     db: &Surreal<Any>,
     page: usize,
@@ -86,7 +133,7 @@ pub async fn query_posts_by_page(
     let mut resp = db
         .query(format!(
             "SELECT * FROM post ORDER BY created_time {order} LIMIT $limit START $start;",
-            order = asc.then(|| "ASC").unwrap_or("DESC")
+            order = if asc { "ASC" } else { "DESC" }
         ))
         .bind(("limit", page_size))
         .bind(("start", page * page_size))
@@ -95,6 +142,7 @@ pub async fn query_posts_by_page(
     Ok(posts)
 }
 
+#[tracing::instrument(skip_all, fields(%id))]
 pub async fn delete_post(db: &Surreal<Any>, id: SmolStr) -> anyhow::Result<()> {
     db.delete(("post", &*id))
         .await
@@ -102,6 +150,7 @@ pub async fn delete_post(db: &Surreal<Any>, id: SmolStr) -> anyhow::Result<()> {
         .map(|_: Option<PostRecord>| ())
 }
 
+#[tracing::instrument(skip_all, fields(%id))]
 pub async fn update_post(
     db: &Surreal<Any>,
     id: SmolStr,
@@ -0,0 +1,37 @@
+use surrealdb::{Surreal, engine::any::Any};
+
+/// 签名会话cookie的密钥, 第一次读取时生成
+#[tracing::instrument(skip_all)]
+pub async fn session_secret(db: &Surreal<Any>) -> anyhow::Result<String> {
+    db.query(
+        "LET $secret = (SELECT session FROM ONLY secret:bulog).session; \
+        IF $secret != NONE { \
+            RETURN $secret; \
+        } ELSE { \
+            LET $secret = rand::string(64); \
+            CREATE secret:bulog SET session = $secret; \
+            RETURN $secret; \
+        }",
+    )
+    .await?
+    .take::<Option<String>>(1)?
+    .ok_or_else(|| anyhow::anyhow!("failed to create session secret"))
+}
+
+/// 会话纪元, 每次修改密码时递增, 纪元不一致的会话视为失效
+#[tracing::instrument(skip_all)]
+pub async fn query_session_epoch(db: &Surreal<Any>) -> anyhow::Result<u64> {
+    db.query("RETURN (SELECT epoch FROM ONLY secret:bulog).epoch")
+        .await?
+        .take::<Option<u64>>(0)
+        .map(|opt| opt.unwrap_or_default())
+        .map_err(Into::into)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn bump_session_epoch(db: &Surreal<Any>) -> anyhow::Result<u64> {
+    db.query("UPSERT ONLY secret:bulog SET epoch = (epoch OR 0) + 1 RETURN VALUE epoch")
+        .await?
+        .take::<Option<u64>>(0)?
+        .ok_or_else(|| anyhow::anyhow!("failed to bump session epoch"))
+}
@@ -0,0 +1,583 @@
+use std::{
+    hash::Hash,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use hashbrown::HashMap;
+use salvo::oapi::ToSchema;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use smol_str::SmolStr;
+use surrealdb::{Surreal, engine::any::Any};
+
+use super::{DynRepo, Repository};
+use crate::{
+    config::CacheConfig,
+    db::model::{
+        comment::{CommentRecord, NewComment},
+        config::{ConfigRecord, ConfigRecordOption, SocialLink},
+        media::{MediaRecord, NewMedia},
+        post::{NewPost, PostRecord, PostRecordOption},
+    },
+};
+
+/// 一类缓存的命中统计
+#[derive(Debug, Default, Clone, Copy, Serialize, ToSchema)]
+#[salvo(schema(name = "SectionStats"))]
+pub struct SectionStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, ToSchema)]
+#[salvo(schema(name = "CacheStats"))]
+pub struct CacheStats {
+    pub config: SectionStats,
+    pub pages: SectionStats,
+    pub rendered: SectionStats,
+}
+
+impl CacheStats {
+    pub fn sections(&self) -> [(&'static str, SectionStats); 3] {
+        [
+            ("config", self.config),
+            ("pages", self.pages),
+            ("rendered", self.rendered),
+        ]
+    }
+}
+
+/// 缓存条目大约占用的字节数
+trait Weight {
+    fn weight(&self) -> usize;
+}
+
+impl Weight for ConfigRecord {
+    fn weight(&self) -> usize {
+        let links = self
+            .social_links
+            .iter()
+            .map(|link| size_of::<SocialLink>() + link.name.len() + link.url.len())
+            .sum::<usize>();
+        size_of::<ConfigRecord>()
+            + self.title.len()
+            + self.description.len()
+            + self.password.len()
+            + self.language.len()
+            + self.timezone.len()
+            + self.footer_html.len()
+            + links
+    }
+}
+
+impl Weight for Vec<PostRecord> {
+    fn weight(&self) -> usize {
+        self.iter()
+            .map(|post| {
+                let tags = post
+                    .tags
+                    .iter()
+                    .map(|tag| size_of::<SmolStr>() + tag.len())
+                    .sum::<usize>();
+                size_of::<PostRecord>()
+                    + post.title.len()
+                    + post.content.len()
+                    + post.id.len()
+                    + post.slug.as_ref().map_or(0, SmolStr::len)
+                    + tags
+            })
+            .sum()
+    }
+}
+
+impl Weight for Arc<str> {
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    used: u64,
+}
+
+struct Slots<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// 所有条目的`weight`之和
+    bytes: usize,
+    /// 每次失效时递增, 查询期间发生过失效的结果不会写入缓存
+    generation: u64,
+    clock: u64,
+}
+
+/// 容量有限的缓存, 条目数或者字节数超出时淘汰最久没有使用的条目
+struct Section<K, V> {
+    slots: Mutex<Slots<K, V>>,
+    capacity: usize,
+    max_bytes: usize,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone + Weight> Section<K, V> {
+    fn new(capacity: usize, max_bytes: usize, ttl: Duration) -> Self {
+        Section {
+            slots: Mutex::new(Slots {
+                entries: HashMap::new(),
+                bytes: 0,
+                generation: 0,
+                clock: 0,
+            }),
+            capacity,
+            max_bytes,
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// 命中时返回缓存的值, 否则返回当前的版本号, 写入时需要带上
+    fn get(&self, key: &K) -> Result<V, u64> {
+        let mut slots = self.slots.lock().unwrap();
+        slots.clock += 1;
+        let clock = slots.clock;
+        let value = match slots.entries.get_mut(key) {
+            Some(entry) if entry.inserted.elapsed() < self.ttl => {
+                entry.used = clock;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                slots.remove(key);
+                None
+            }
+            None => None,
+        };
+        match value {
+            Some(value) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(value)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Err(slots.generation)
+            }
+        }
+    }
+
+    fn insert(&self, key: K, value: V, generation: u64) {
+        let weight = value.weight();
+        if self.capacity == 0 || weight > self.max_bytes {
+            return;
+        }
+        let mut slots = self.slots.lock().unwrap();
+        if slots.generation != generation {
+            return;
+        }
+        slots.remove(&key);
+        while (slots.entries.len() >= self.capacity || slots.bytes + weight > self.max_bytes)
+            && let Some(oldest) = slots
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone())
+        {
+            slots.remove(&oldest);
+        }
+        slots.clock += 1;
+        let used = slots.clock;
+        slots.bytes += weight;
+        slots.entries.insert(
+            key,
+            Entry {
+                value,
+                inserted: Instant::now(),
+                used,
+            },
+        );
+    }
+
+    async fn get_or_load<F>(&self, key: K, load: F) -> anyhow::Result<V>
+    where
+        F: Future<Output = anyhow::Result<V>>,
+    {
+        match self.get(&key) {
+            Ok(value) => Ok(value),
+            Err(generation) => {
+                let value = load.await?;
+                self.insert(key, value.clone(), generation);
+                Ok(value)
+            }
+        }
+    }
+
+    fn clear(&self) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.entries.clear();
+        slots.bytes = 0;
+        slots.generation += 1;
+    }
+
+    fn stats(&self) -> SectionStats {
+        SectionStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.slots.lock().unwrap().entries.len(),
+        }
+    }
+}
+
+impl<K, V: Weight> Slots<K, V>
+where
+    K: Hash + Eq,
+{
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.value.weight();
+        }
+    }
+}
+
+/// 进程内的查询缓存, 修改数据的接口会让相关的缓存失效
+///
+/// 其他进程直接修改数据库时, 缓存最多在`ttl_secs`之后更新
+pub struct Cache {
+    config: Section<(), ConfigRecord>,
+    /// 以`(page, page_size, asc)`为key的文章列表, 文章变化时全部失效
+    pages: Section<(usize, usize, bool), Vec<PostRecord>>,
+    /// 以markdown的哈希为key, 内容不变时结果不会过期
+    rendered: Section<[u8; 32], Arc<str>>,
+}
+
+impl Cache {
+    pub fn new(config: &CacheConfig) -> Self {
+        Cache {
+            config: Section::new(config.max_entries.min(1), config.max_bytes, config.ttl()),
+            pages: Section::new(config.max_entries, config.max_bytes, config.ttl()),
+            rendered: Section::new(config.max_entries, config.max_bytes, Duration::MAX),
+        }
+    }
+
+    /// 把markdown渲染为html, 相同的内容只渲染一次
+    pub fn render(&self, markdown: &str, render: impl FnOnce(&str) -> String) -> Arc<str> {
+        let key: [u8; 32] = Sha256::digest(markdown.as_bytes()).into();
+        match self.rendered.get(&key) {
+            Ok(html) => html,
+            Err(generation) => {
+                let html: Arc<str> = render(markdown).into();
+                self.rendered.insert(key, html.clone(), generation);
+                html
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            config: self.config.stats(),
+            pages: self.pages.stats(),
+            rendered: self.rendered.stats(),
+        }
+    }
+}
+
+/// 在其他后端外面加上一层缓存
+pub struct CachedRepo {
+    inner: DynRepo,
+    cache: Cache,
+}
+
+impl CachedRepo {
+    /// `max_entries`为0时不使用缓存, 直接返回原来的后端
+    pub fn wrap(inner: DynRepo, config: &CacheConfig) -> DynRepo {
+        if config.max_entries == 0 {
+            return inner;
+        }
+        Arc::new(CachedRepo {
+            inner,
+            cache: Cache::new(config),
+        })
+    }
+}
+
+#[async_trait]
+impl Repository for CachedRepo {
+    fn surreal(&self) -> Option<&Surreal<Any>> {
+        self.inner.surreal()
+    }
+
+    fn cache(&self) -> Option<&Cache> {
+        Some(&self.cache)
+    }
+
+    async fn health(&self) -> bool {
+        self.inner.health().await
+    }
+
+    async fn is_new_install(&self) -> anyhow::Result<bool> {
+        self.inner.is_new_install().await
+    }
+
+    async fn create_config(&self, config: ConfigRecord) -> anyhow::Result<()> {
+        let result = self.inner.create_config(config).await;
+        self.cache.config.clear();
+        result
+    }
+
+    async fn query_config(&self) -> anyhow::Result<ConfigRecord> {
+        self.cache
+            .config
+            .get_or_load((), self.inner.query_config())
+            .await
+    }
+
+    async fn update_config(&self, config: ConfigRecordOption) -> anyhow::Result<()> {
+        let result = self.inner.update_config(config).await;
+        self.cache.config.clear();
+        result
+    }
+
+    async fn update_password(&self, pwd: String) -> anyhow::Result<()> {
+        let result = self.inner.update_password(pwd).await;
+        self.cache.config.clear();
+        result
+    }
+
+    async fn verify_password(&self, pwd: String) -> anyhow::Result<bool> {
+        self.inner.verify_password(pwd).await
+    }
+
+    async fn session_secret(&self) -> anyhow::Result<String> {
+        self.inner.session_secret().await
+    }
+
+    async fn query_session_epoch(&self) -> anyhow::Result<u64> {
+        self.inner.query_session_epoch().await
+    }
+
+    async fn bump_session_epoch(&self) -> anyhow::Result<u64> {
+        self.inner.bump_session_epoch().await
+    }
+
+    async fn insert_post(&self, post: NewPost) -> anyhow::Result<SmolStr> {
+        let result = self.inner.insert_post(post).await;
+        self.cache.pages.clear();
+        result
+    }
+
+    async fn query_post(&self, id: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        self.inner.query_post(id).await
+    }
+
+    async fn query_post_by_slug(&self, slug: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        self.inner.query_post_by_slug(slug).await
+    }
+
+    async fn query_all_posts(&self) -> anyhow::Result<Vec<PostRecord>> {
+        self.inner.query_all_posts().await
+    }
+
+    async fn query_posts_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        asc: bool,
+    ) -> anyhow::Result<Vec<PostRecord>> {
+        self.cache
+            .pages
+            .get_or_load(
+                (page, page_size, asc),
+                self.inner.query_posts_by_page(page, page_size, asc),
+            )
+            .await
+    }
+
+    async fn update_post(&self, id: SmolStr, post: PostRecordOption) -> anyhow::Result<()> {
+        let result = self.inner.update_post(id, post).await;
+        self.cache.pages.clear();
+        result
+    }
+
+    async fn delete_post(&self, id: SmolStr) -> anyhow::Result<()> {
+        let result = self.inner.delete_post(id).await;
+        self.cache.pages.clear();
+        result
+    }
+
+    async fn create_media(&self, media: NewMedia) -> anyhow::Result<(MediaRecord, bool)> {
+        self.inner.create_media(media).await
+    }
+
+    async fn query_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        self.inner.query_media(id).await
+    }
+
+    async fn query_media_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+    ) -> anyhow::Result<Vec<MediaRecord>> {
+        self.inner.query_media_by_page(page, page_size).await
+    }
+
+    async fn delete_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        self.inner.delete_media(id).await
+    }
+
+    async fn create_comment(&self, comment: NewComment) -> anyhow::Result<SmolStr> {
+        self.inner.create_comment(comment).await
+    }
+
+    async fn query_comments_by_post(&self, post: SmolStr) -> anyhow::Result<Vec<CommentRecord>> {
+        self.inner.query_comments_by_post(post).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::{
+        config::CacheConfig,
+        db::{
+            model::{
+                config::{ConfigRecord, ConfigRecordOption},
+                post::{NewPost, PostRecordOption},
+            },
+            test_db,
+        },
+        site::render::markdown_html,
+    };
+
+    use super::{CachedRepo, Section, Weight};
+
+    impl Weight for u32 {
+        fn weight(&self) -> usize {
+            *self as usize
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_repo() -> anyhow::Result<()> {
+        let repo = CachedRepo::wrap(Arc::new(test_db().await?), &CacheConfig::default());
+        let cache = repo.cache().unwrap();
+
+        assert_eq!(repo.query_config().await?.title, "bulog");
+        assert_eq!(repo.query_config().await?.title, "bulog");
+        let stats = cache.stats().config;
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        repo.update_config(ConfigRecordOption {
+            title: Some("cached".to_owned()),
+            ..Default::default()
+        })
+        .await?;
+        assert_eq!(cache.stats().config.entries, 0);
+        assert_eq!(repo.query_config().await?.title, "cached");
+
+        let id = repo
+            .insert_post(NewPost {
+                title: "cached".into(),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(
+            repo.query_posts_by_page(0, 10, false).await?[0].title,
+            "cached"
+        );
+        assert_eq!(
+            repo.query_posts_by_page(0, 10, false).await?[0].title,
+            "cached"
+        );
+        assert_eq!(cache.stats().pages.hits, 1);
+        // 修改, 新增和删除文章后列表立即更新
+        repo.update_post(
+            id.clone(),
+            PostRecordOption {
+                title: Some("updated".into()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert_eq!(cache.stats().pages.entries, 0);
+        assert_eq!(
+            repo.query_posts_by_page(0, 10, false).await?[0].title,
+            "updated"
+        );
+        let new = repo
+            .insert_post(NewPost {
+                title: "new".into(),
+                ..Default::default()
+            })
+            .await?;
+        assert_eq!(repo.query_posts_by_page(0, 10, false).await?.len(), 2);
+        repo.delete_post(new).await?;
+        assert_eq!(repo.query_posts_by_page(0, 10, false).await?.len(), 1);
+
+        let first = cache.render("**bold**", markdown_html);
+        let second = cache.render("**bold**", |_| unreachable!());
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(&*first, "<p><strong>bold</strong></p>\n");
+
+        let uncached = CachedRepo::wrap(
+            Arc::new(test_db().await?),
+            &CacheConfig {
+                max_entries: 0,
+                ..Default::default()
+            },
+        );
+        assert!(uncached.cache().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_section() {
+        let section = Section::<u32, u32>::new(2, 100, Duration::from_secs(60));
+        for key in 0..2 {
+            let generation = section.get(&key).unwrap_err();
+            section.insert(key, key, generation);
+        }
+        assert_eq!(section.get(&0), Ok(0));
+        // 容量已满时淘汰最久没有使用的1
+        let generation = section.get(&2).unwrap_err();
+        section.insert(2, 2, generation);
+        assert!(section.get(&1).is_err());
+        assert_eq!(section.get(&0), Ok(0));
+        assert_eq!(section.stats().entries, 2);
+
+        // 查询期间缓存失效, 旧的结果不能写入
+        let generation = section.get(&3).unwrap_err();
+        section.clear();
+        section.insert(3, 3, generation);
+        assert!(section.get(&3).is_err());
+
+        // 字节数超出时同样淘汰旧的条目, 太大的条目不会写入
+        let sized = Section::<u32, u32>::new(10, 100, Duration::from_secs(60));
+        for key in [60, 30, 50, 200] {
+            let generation = sized.get(&key).unwrap_err();
+            sized.insert(key, key, generation);
+        }
+        assert!(sized.get(&60).is_err());
+        assert!(sized.get(&200).is_err());
+        assert_eq!(sized.get(&30), Ok(30));
+        assert_eq!(sized.get(&50), Ok(50));
+
+        // 设置的大小包含字符串的内容
+        let config = ConfigRecord {
+            footer_html: "a".repeat(1024),
+            ..Default::default()
+        };
+        assert!(config.weight() > 1024 + size_of::<ConfigRecord>());
+
+        let expired = Section::<u32, u32>::new(2, 100, Duration::ZERO);
+        let generation = expired.get(&0).unwrap_err();
+        expired.insert(0, 0, generation);
+        assert!(expired.get(&0).is_err());
+    }
+}
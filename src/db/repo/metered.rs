@@ -0,0 +1,179 @@
+use std::{sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use smol_str::SmolStr;
+use surrealdb::{Surreal, engine::any::Any};
+
+use super::{DynRepo, Repository};
+use crate::{
+    db::model::{
+        comment::{CommentRecord, NewComment},
+        config::{ConfigRecord, ConfigRecordOption},
+        media::{MediaRecord, NewMedia},
+        post::{NewPost, PostRecord, PostRecordOption},
+    },
+    metrics::Metrics,
+};
+
+/// 记录每次访问数据库的耗时, 放在缓存内层, 命中缓存的查询不会被记录
+pub struct MeteredRepo {
+    inner: DynRepo,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredRepo {
+    pub fn wrap(inner: DynRepo, metrics: Arc<Metrics>) -> DynRepo {
+        Arc::new(MeteredRepo { inner, metrics })
+    }
+
+    async fn timed<T>(
+        &self,
+        operation: &'static str,
+        query: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let start = Instant::now();
+        let result = query.await;
+        self.metrics
+            .observe_query(operation, result.is_ok(), start.elapsed());
+        result
+    }
+}
+
+#[async_trait]
+impl Repository for MeteredRepo {
+    fn surreal(&self) -> Option<&Surreal<Any>> {
+        self.inner.surreal()
+    }
+
+    async fn health(&self) -> bool {
+        let start = Instant::now();
+        let healthy = self.inner.health().await;
+        self.metrics
+            .observe_query("health", healthy, start.elapsed());
+        healthy
+    }
+
+    async fn is_new_install(&self) -> anyhow::Result<bool> {
+        self.timed("is_new_install", self.inner.is_new_install())
+            .await
+    }
+
+    async fn create_config(&self, config: ConfigRecord) -> anyhow::Result<()> {
+        self.timed("create_config", self.inner.create_config(config))
+            .await
+    }
+
+    async fn query_config(&self) -> anyhow::Result<ConfigRecord> {
+        self.timed("query_config", self.inner.query_config()).await
+    }
+
+    async fn update_config(&self, config: ConfigRecordOption) -> anyhow::Result<()> {
+        self.timed("update_config", self.inner.update_config(config))
+            .await
+    }
+
+    async fn update_password(&self, pwd: String) -> anyhow::Result<()> {
+        self.timed("update_password", self.inner.update_password(pwd))
+            .await
+    }
+
+    async fn verify_password(&self, pwd: String) -> anyhow::Result<bool> {
+        self.timed("verify_password", self.inner.verify_password(pwd))
+            .await
+    }
+
+    async fn session_secret(&self) -> anyhow::Result<String> {
+        self.timed("session_secret", self.inner.session_secret())
+            .await
+    }
+
+    async fn query_session_epoch(&self) -> anyhow::Result<u64> {
+        self.timed("query_session_epoch", self.inner.query_session_epoch())
+            .await
+    }
+
+    async fn bump_session_epoch(&self) -> anyhow::Result<u64> {
+        self.timed("bump_session_epoch", self.inner.bump_session_epoch())
+            .await
+    }
+
+    async fn insert_post(&self, post: NewPost) -> anyhow::Result<SmolStr> {
+        self.timed("insert_post", self.inner.insert_post(post))
+            .await
+    }
+
+    async fn query_post(&self, id: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        self.timed("query_post", self.inner.query_post(id)).await
+    }
+
+    async fn query_post_by_slug(&self, slug: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        self.timed("query_post_by_slug", self.inner.query_post_by_slug(slug))
+            .await
+    }
+
+    async fn query_all_posts(&self) -> anyhow::Result<Vec<PostRecord>> {
+        self.timed("query_all_posts", self.inner.query_all_posts())
+            .await
+    }
+
+    async fn query_posts_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        asc: bool,
+    ) -> anyhow::Result<Vec<PostRecord>> {
+        self.timed(
+            "query_posts_by_page",
+            self.inner.query_posts_by_page(page, page_size, asc),
+        )
+        .await
+    }
+
+    async fn update_post(&self, id: SmolStr, post: PostRecordOption) -> anyhow::Result<()> {
+        self.timed("update_post", self.inner.update_post(id, post))
+            .await
+    }
+
+    async fn delete_post(&self, id: SmolStr) -> anyhow::Result<()> {
+        self.timed("delete_post", self.inner.delete_post(id)).await
+    }
+
+    async fn create_media(&self, media: NewMedia) -> anyhow::Result<(MediaRecord, bool)> {
+        self.timed("create_media", self.inner.create_media(media))
+            .await
+    }
+
+    async fn query_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        self.timed("query_media", self.inner.query_media(id)).await
+    }
+
+    async fn query_media_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+    ) -> anyhow::Result<Vec<MediaRecord>> {
+        self.timed(
+            "query_media_by_page",
+            self.inner.query_media_by_page(page, page_size),
+        )
+        .await
+    }
+
+    async fn delete_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        self.timed("delete_media", self.inner.delete_media(id))
+            .await
+    }
+
+    async fn create_comment(&self, comment: NewComment) -> anyhow::Result<SmolStr> {
+        self.timed("create_comment", self.inner.create_comment(comment))
+            .await
+    }
+
+    async fn query_comments_by_post(&self, post: SmolStr) -> anyhow::Result<Vec<CommentRecord>> {
+        self.timed(
+            "query_comments_by_post",
+            self.inner.query_comments_by_post(post),
+        )
+        .await
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use smol_str::SmolStr;
+use surrealdb::{Surreal, engine::any::Any};
+
+use super::model::{
+    comment::{CommentRecord, NewComment},
+    config::{ConfigRecord, ConfigRecordOption},
+    media::{MediaRecord, NewMedia},
+    post::{NewPost, PostRecord, PostRecordOption},
+};
+use crate::config::DatabaseConfig;
+
+pub use cache::{Cache, CacheStats, CachedRepo};
+pub use metered::MeteredRepo;
+#[cfg(feature = "sqlite_backend")]
+pub use sqlite::{InvalidField, SqliteRepo};
+
+mod cache;
+mod metered;
+#[cfg(feature = "sqlite_backend")]
+mod sqlite;
+mod surreal;
+
+/// 使用SQLite保存数据的地址前缀, 例如`sqlite://./bulog.db`
+pub const SQLITE_SCHEME: &str = "sqlite://";
+
+/// 博客数据的存取接口, 语义与`db::model`中的同名函数一致
+///
+/// `Surreal<Any>`直接实现了这个trait, 导入导出和备份等依赖SurrealQL的功能仍然需要SurrealDB
+// 文章和评论相关接口还没有接入路由
+#[allow(dead_code)]
+#[async_trait]
+pub trait Repository: Send + Sync + 'static {
+    /// 使用SurrealDB时返回客户端
+    fn surreal(&self) -> Option<&Surreal<Any>> {
+        None
+    }
+
+    /// 带有缓存时返回缓存, 用于读取统计和缓存渲染结果
+    fn cache(&self) -> Option<&Cache> {
+        None
+    }
+
+    /// 数据库能否正常响应
+    async fn health(&self) -> bool;
+
+    async fn is_new_install(&self) -> anyhow::Result<bool>;
+
+    /// 已存在配置时报错, 并发安装只有一个能成功
+    async fn create_config(&self, config: ConfigRecord) -> anyhow::Result<()>;
+
+    async fn query_config(&self) -> anyhow::Result<ConfigRecord>;
+
+    async fn update_config(&self, config: ConfigRecordOption) -> anyhow::Result<()>;
+
+    async fn update_password(&self, pwd: String) -> anyhow::Result<()>;
+
+    async fn verify_password(&self, pwd: String) -> anyhow::Result<bool>;
+
+    /// 签名会话cookie的密钥, 第一次读取时生成
+    async fn session_secret(&self) -> anyhow::Result<String>;
+
+    async fn query_session_epoch(&self) -> anyhow::Result<u64>;
+
+    async fn bump_session_epoch(&self) -> anyhow::Result<u64>;
+
+    async fn insert_post(&self, post: NewPost) -> anyhow::Result<SmolStr>;
+
+    async fn query_post(&self, id: SmolStr) -> anyhow::Result<Option<PostRecord>>;
+
+    async fn query_post_by_slug(&self, slug: SmolStr) -> anyhow::Result<Option<PostRecord>>;
+
+    async fn query_all_posts(&self) -> anyhow::Result<Vec<PostRecord>>;
+
+    async fn query_posts_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        asc: bool,
+    ) -> anyhow::Result<Vec<PostRecord>>;
+
+    async fn update_post(&self, id: SmolStr, post: PostRecordOption) -> anyhow::Result<()>;
+
+    async fn delete_post(&self, id: SmolStr) -> anyhow::Result<()>;
+
+    /// 相同内容的文件只保存一份, 返回的`bool`表示是否新建了记录
+    async fn create_media(&self, media: NewMedia) -> anyhow::Result<(MediaRecord, bool)>;
+
+    async fn query_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>>;
+
+    async fn query_media_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+    ) -> anyhow::Result<Vec<MediaRecord>>;
+
+    /// 返回被删除的记录, 调用者负责删除存储后端中的文件
+    async fn delete_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>>;
+
+    async fn create_comment(&self, comment: NewComment) -> anyhow::Result<SmolStr>;
+
+    async fn query_comments_by_post(&self, post: SmolStr) -> anyhow::Result<Vec<CommentRecord>>;
+}
+
+pub type DynRepo = Arc<dyn Repository>;
+
+/// 根据地址选择后端, `sqlite://`以外的地址都交给SurrealDB
+pub async fn open(config: &DatabaseConfig) -> anyhow::Result<DynRepo> {
+    if let Some(path) = config.endpoint.strip_prefix(SQLITE_SCHEME) {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "sqlite_backend")] {
+                return Ok(Arc::new(SqliteRepo::open(path).await?));
+            } else {
+                let _ = path;
+                anyhow::bail!("sqlite endpoint requires the `sqlite_backend` feature");
+            }
+        }
+    }
+    Ok(Arc::new(super::open(config).await?))
+}
+
+/// 只有SurrealDB支持的功能, 其他后端返回错误
+pub fn require_surreal(repo: &dyn Repository) -> anyhow::Result<&Surreal<Any>> {
+    repo.surreal()
+        .ok_or_else(|| anyhow::anyhow!("this feature requires the SurrealDB backend"))
+}
@@ -0,0 +1,795 @@
+use std::sync::{Arc, Mutex};
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{
+        SaltString,
+        rand_core::{OsRng, RngCore},
+    },
+};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{
+    Connection, OptionalExtension, Row, params,
+    types::{FromSql, FromSqlError, FromSqlResult, ValueRef},
+};
+use smol_str::SmolStr;
+
+use super::Repository;
+use crate::{
+    db::model::{
+        comment::{CommentRecord, NewComment},
+        config::{ConfigRecord, ConfigRecordOption, RepeatInstallation, SETTINGS_VERSION},
+        media::{MediaRecord, NewMedia},
+        post::{NewPost, PostRecord, PostRecordOption},
+    },
+    nano_id::nanoid,
+};
+
+/// 表结构的迁移脚本, 版本记录在`PRAGMA user_version`中, 规则与`db::schema`相同
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE config (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        data TEXT NOT NULL,
+        password TEXT NOT NULL,
+        version INTEGER NOT NULL
+    );
+    CREATE TABLE secret (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        session TEXT,
+        epoch INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE post (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_time TEXT NOT NULL,
+        draft INTEGER NOT NULL,
+        pinned INTEGER NOT NULL,
+        tags TEXT NOT NULL,
+        slug TEXT
+    );
+    CREATE INDEX post_created_time ON post (created_time);
+    CREATE INDEX post_slug ON post (slug);
+    CREATE TABLE media (
+        id TEXT PRIMARY KEY,
+        filename TEXT NOT NULL,
+        mime TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        width INTEGER,
+        height INTEGER,
+        hash TEXT NOT NULL UNIQUE,
+        created_time TEXT NOT NULL
+    );
+    CREATE INDEX media_created_time ON media (created_time);
+    CREATE TABLE comment (
+        id TEXT PRIMARY KEY,
+        post TEXT NOT NULL,
+        parent TEXT,
+        author TEXT NOT NULL,
+        email TEXT NOT NULL,
+        url TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_time TEXT NOT NULL,
+        approved INTEGER NOT NULL
+    );
+    CREATE INDEX comment_post ON comment (post);
+"];
+
+/// 单个连接加锁使用, 查询在阻塞线程池中执行
+pub struct SqliteRepo {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteRepo {
+    /// `path`为`:memory:`时使用内存数据库
+    pub async fn open(path: &str) -> anyhow::Result<SqliteRepo> {
+        let path = path.to_owned();
+        let conn = tokio::task::spawn_blocking(move || -> anyhow::Result<Connection> {
+            let mut conn = if path == ":memory:" {
+                Connection::open_in_memory()?
+            } else {
+                let conn = Connection::open(&path)?;
+                conn.pragma_update(None, "journal_mode", "WAL")?;
+                conn
+            };
+            conn.busy_timeout(std::time::Duration::from_secs(5))?;
+            migrate(&mut conn)?;
+            Ok(conn)
+        })
+        .await??;
+        Ok(SqliteRepo {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    async fn call<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|err| err.into_inner());
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        anyhow::bail!(
+            "database schema version {version} is newer than supported version {}",
+            MIGRATIONS.len()
+        );
+    }
+    for (from, script) in MIGRATIONS.iter().enumerate().skip(version) {
+        tracing::info!(
+            "migrating database schema from version {from} to {}",
+            from + 1
+        );
+        let tx = conn.transaction()?;
+        tx.execute_batch(script)?;
+        tx.pragma_update(None, "user_version", from + 1)?;
+        tx.commit()?;
+    }
+
+    // 设置以json保存, 缺失的字段读取时使用默认值, 只需要检查和更新版本号
+    let settings: Option<(String, usize)> = conn
+        .query_row("SELECT data, version FROM config WHERE id = 1", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()?;
+    if let Some((data, version)) = settings {
+        if version > SETTINGS_VERSION {
+            anyhow::bail!(
+                "site settings version {version} is newer than supported version {SETTINGS_VERSION}"
+            );
+        }
+        if version < SETTINGS_VERSION {
+            let config: ConfigRecord = sonic_rs::from_str(&data)?;
+            conn.execute(
+                "UPDATE config SET data = ?1, version = ?2 WHERE id = 1",
+                params![sonic_rs::to_string(&config)?, SETTINGS_VERSION],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// 固定长度的UTC时间, 按字符串排序就是按时间排序
+fn time_text(time: &surrealdb::Datetime) -> String {
+    time.into_inner_ref()
+        .0
+        .to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn now_text() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+struct Time(surrealdb::Datetime);
+
+impl FromSql for Time {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        DateTime::parse_from_rfc3339(value.as_str()?)
+            .map(|time| Time(time.with_timezone(&Utc).into()))
+            .map_err(|err| FromSqlError::Other(Box::new(err)))
+    }
+}
+
+struct Tags(Vec<SmolStr>);
+
+impl FromSql for Tags {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        sonic_rs::from_str(value.as_str()?)
+            .map(Tags)
+            .map_err(|err| FromSqlError::Other(Box::new(err)))
+    }
+}
+
+fn text(row: &Row, column: &str) -> rusqlite::Result<SmolStr> {
+    row.get::<_, String>(column).map(Into::into)
+}
+
+fn optional_text(row: &Row, column: &str) -> rusqlite::Result<Option<SmolStr>> {
+    row.get::<_, Option<String>>(column)
+        .map(|text| text.map(Into::into))
+}
+
+fn post_from_row(row: &Row) -> rusqlite::Result<PostRecord> {
+    Ok(PostRecord {
+        id: text(row, "id")?,
+        title: text(row, "title")?,
+        content: text(row, "content")?,
+        created_time: row.get::<_, Time>("created_time")?.0,
+        draft: row.get("draft")?,
+        pinned: row.get("pinned")?,
+        tags: row.get::<_, Tags>("tags")?.0,
+        slug: optional_text(row, "slug")?,
+    })
+}
+
+fn media_from_row(row: &Row) -> rusqlite::Result<MediaRecord> {
+    Ok(MediaRecord {
+        id: text(row, "id")?,
+        filename: text(row, "filename")?,
+        mime: text(row, "mime")?,
+        size: row.get("size")?,
+        width: row.get("width")?,
+        height: row.get("height")?,
+        hash: text(row, "hash")?,
+        created_time: row.get::<_, Time>("created_time")?.0,
+    })
+}
+
+fn comment_from_row(row: &Row) -> rusqlite::Result<CommentRecord> {
+    Ok(CommentRecord {
+        id: text(row, "id")?,
+        post: text(row, "post")?,
+        parent: optional_text(row, "parent")?,
+        author: text(row, "author")?,
+        email: text(row, "email")?,
+        url: text(row, "url")?,
+        content: text(row, "content")?,
+        created_time: row.get::<_, Time>("created_time")?.0,
+        approved: row.get("approved")?,
+    })
+}
+
+/// 违反表结构中的约束, 对应SurrealDB中断言失败的错误, 接口返回400
+#[derive(Debug)]
+pub struct InvalidField(pub &'static str);
+
+impl std::fmt::Display for InvalidField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid value for field `{}`", self.0)
+    }
+}
+
+impl std::error::Error for InvalidField {}
+
+fn ensure(valid: bool, field: &'static str) -> Result<(), InvalidField> {
+    if valid {
+        Ok(())
+    } else {
+        Err(InvalidField(field))
+    }
+}
+
+fn len(value: &str) -> usize {
+    value.chars().count()
+}
+
+// 以下检查与`0002_schemafull.surql`中的断言保持一致
+
+fn check_config(config: &ConfigRecord) -> Result<(), InvalidField> {
+    ensure(
+        !config.title.trim().is_empty() && len(&config.title) <= 128,
+        "title",
+    )?;
+    ensure(len(&config.description) <= 1024, "description")?;
+    ensure(
+        !config.language.is_empty()
+            && len(&config.language) <= 35
+            && config
+                .language
+                .chars()
+                .all(|c| c == '-' || c.is_alphanumeric())
+            && config.language.chars().any(char::is_alphanumeric),
+        "language",
+    )?;
+    ensure(
+        !config.timezone.is_empty() && len(&config.timezone) <= 64,
+        "timezone",
+    )?;
+    ensure((1..=100).contains(&config.posts_per_page), "posts_per_page")?;
+    ensure(len(&config.footer_html) <= 16384, "footer_html")?;
+    ensure(config.social_links.len() <= 32, "social_links")?;
+    for link in &config.social_links {
+        ensure(!link.name.trim().is_empty(), "social_links[*].name")?;
+        ensure(
+            ["http://", "https://", "mailto:"]
+                .iter()
+                .any(|scheme| link.url.starts_with(scheme)),
+            "social_links[*].url",
+        )?;
+    }
+    ensure((1..=100).contains(&config.feed.items), "feed.items")
+}
+
+fn check_post(
+    title: &str,
+    content: &str,
+    tags: &[SmolStr],
+    slug: Option<&str>,
+) -> Result<(), InvalidField> {
+    ensure(!title.trim().is_empty() && len(title) <= 256, "title")?;
+    ensure(len(content) <= 1048576, "content")?;
+    ensure(tags.len() <= 64, "tags")?;
+    for tag in tags {
+        ensure(!tag.trim().is_empty() && len(tag) <= 64, "tags[*]")?;
+    }
+    ensure(
+        slug.is_none_or(|slug| !slug.is_empty() && len(slug) <= 256),
+        "slug",
+    )
+}
+
+fn check_media(media: &NewMedia) -> Result<(), InvalidField> {
+    ensure(
+        !media.filename.is_empty() && len(&media.filename) <= 1024,
+        "filename",
+    )?;
+    ensure(!media.hash.is_empty(), "hash")
+}
+
+fn check_comment(comment: &NewComment) -> Result<(), InvalidField> {
+    ensure(!comment.post.is_empty(), "post")?;
+    ensure(len(&comment.author) <= 256, "author")?;
+    ensure(len(&comment.email) <= 256, "email")?;
+    ensure(len(&comment.url) <= 1024, "url")?;
+    ensure(
+        !comment.content.trim().is_empty() && len(&comment.content) <= 65536,
+        "content",
+    )
+}
+
+fn hash_password(pwd: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(pwd.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| anyhow::anyhow!("failed to hash password: {err}"))
+}
+
+fn random_string(len: usize) -> String {
+    const ALPHANUMERIC: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let mut bytes = vec![0; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+        .into_iter()
+        .map(|byte| ALPHANUMERIC[byte as usize % ALPHANUMERIC.len()] as char)
+        .collect()
+}
+
+fn read_config(conn: &Connection) -> anyhow::Result<Option<ConfigRecord>> {
+    let data: Option<String> = conn
+        .query_row("SELECT data FROM config WHERE id = 1", [], |row| row.get(0))
+        .optional()?;
+    data.map(|data| sonic_rs::from_str(&data).map_err(Into::into))
+        .transpose()
+}
+
+#[async_trait]
+impl Repository for SqliteRepo {
+    async fn health(&self) -> bool {
+        self.call(|conn| Ok(conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))?))
+            .await
+            .is_ok()
+    }
+
+    async fn is_new_install(&self) -> anyhow::Result<bool> {
+        self.call(|conn| Ok(read_config(conn)?.is_none())).await
+    }
+
+    async fn create_config(&self, mut config: ConfigRecord) -> anyhow::Result<()> {
+        let pwd = std::mem::take(&mut config.password);
+        check_config(&config)?;
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            if read_config(&tx)?.is_some() {
+                return Err(RepeatInstallation.into());
+            }
+            tx.execute(
+                "INSERT INTO config (id, data, password, version) VALUES (1, ?1, ?2, ?3)",
+                params![
+                    sonic_rs::to_string(&config)?,
+                    hash_password(&pwd)?,
+                    SETTINGS_VERSION
+                ],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn query_config(&self) -> anyhow::Result<ConfigRecord> {
+        self.call(|conn| {
+            read_config(conn)?.ok_or_else(|| anyhow::anyhow!("Uninitialized blog info"))
+        })
+        .await
+    }
+
+    async fn update_config(&self, patch: ConfigRecordOption) -> anyhow::Result<()> {
+        // 解构时不使用`..`, 新增字段时编译器会提醒这里也需要处理
+        let ConfigRecordOption {
+            title,
+            description,
+            password,
+            language,
+            timezone,
+            posts_per_page,
+            footer_html,
+            social_links,
+            comment_policy,
+            feed,
+        } = patch;
+        if let Some(pwd) = password {
+            self.update_password(pwd).await?;
+        }
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let Some(mut config) = read_config(&tx)? else {
+                return Ok(());
+            };
+            macro_rules! merge {
+                ($($field:ident),*) => {
+                    $(if let Some(value) = $field {
+                        config.$field = value;
+                    })*
+                };
+            }
+            merge!(
+                title,
+                description,
+                language,
+                timezone,
+                posts_per_page,
+                footer_html,
+                social_links,
+                comment_policy,
+                feed
+            );
+            check_config(&config)?;
+            tx.execute(
+                "UPDATE config SET data = ?1 WHERE id = 1",
+                params![sonic_rs::to_string(&config)?],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_password(&self, pwd: String) -> anyhow::Result<()> {
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE config SET password = ?1 WHERE id = 1",
+                params![hash_password(&pwd)?],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn verify_password(&self, pwd: String) -> anyhow::Result<bool> {
+        self.call(move |conn| {
+            let hash: Option<String> = conn
+                .query_row("SELECT password FROM config WHERE id = 1", [], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+            let Some(hash) = hash else {
+                return Ok(false);
+            };
+            let hash = PasswordHash::new(&hash)
+                .map_err(|err| anyhow::anyhow!("invalid password hash: {err}"))?;
+            Ok(Argon2::default()
+                .verify_password(pwd.as_bytes(), &hash)
+                .is_ok())
+        })
+        .await
+    }
+
+    async fn session_secret(&self) -> anyhow::Result<String> {
+        self.call(|conn| {
+            conn.execute(
+                "INSERT INTO secret (id, session) VALUES (1, ?1) \
+                ON CONFLICT (id) DO UPDATE SET session = coalesce(session, excluded.session)",
+                params![random_string(64)],
+            )?;
+            Ok(
+                conn.query_row("SELECT session FROM secret WHERE id = 1", [], |row| {
+                    row.get(0)
+                })?,
+            )
+        })
+        .await
+    }
+
+    async fn query_session_epoch(&self) -> anyhow::Result<u64> {
+        self.call(|conn| {
+            Ok(conn
+                .query_row("SELECT epoch FROM secret WHERE id = 1", [], |row| {
+                    row.get(0)
+                })
+                .optional()?
+                .unwrap_or_default())
+        })
+        .await
+    }
+
+    async fn bump_session_epoch(&self) -> anyhow::Result<u64> {
+        self.call(|conn| {
+            Ok(conn.query_row(
+                "INSERT INTO secret (id, epoch) VALUES (1, 1) \
+                ON CONFLICT (id) DO UPDATE SET epoch = epoch + 1 RETURNING epoch",
+                [],
+                |row| row.get(0),
+            )?)
+        })
+        .await
+    }
+
+    async fn insert_post(&self, post: NewPost) -> anyhow::Result<SmolStr> {
+        check_post(&post.title, &post.content, &post.tags, post.slug.as_deref())?;
+        self.call(move |conn| {
+            let created_time = post
+                .created_time
+                .as_ref()
+                .map_or_else(now_text, time_text);
+            let tags = sonic_rs::to_string(&post.tags)?;
+            loop {
+                let id = nanoid(6);
+                let inserted = conn.execute(
+                    "INSERT INTO post (id, title, content, created_time, draft, pinned, tags, slug) \
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) ON CONFLICT (id) DO NOTHING",
+                    params![
+                        id.as_str(),
+                        post.title.as_str(),
+                        post.content.as_str(),
+                        created_time,
+                        post.draft,
+                        post.pinned,
+                        tags,
+                        post.slug.as_deref()
+                    ],
+                )?;
+                if inserted == 1 {
+                    break Ok(id);
+                }
+            }
+        })
+        .await
+    }
+
+    async fn query_post(&self, id: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT * FROM post WHERE id = ?1",
+                    [id.as_str()],
+                    post_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn query_post_by_slug(&self, slug: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT * FROM post WHERE slug = ?1 LIMIT 1",
+                    [slug.as_str()],
+                    post_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn query_all_posts(&self) -> anyhow::Result<Vec<PostRecord>> {
+        self.call(|conn| {
+            let mut stmt = conn.prepare("SELECT * FROM post ORDER BY id")?;
+            let posts = stmt
+                .query_map([], post_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(posts)
+        })
+        .await
+    }
+
+    async fn query_posts_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        asc: bool,
+    ) -> anyhow::Result<Vec<PostRecord>> {
+        self.call(move |conn| {
+            let order = if asc { "ASC" } else { "DESC" };
+            let mut stmt = conn.prepare(&format!(
+                "SELECT * FROM post ORDER BY created_time {order}, rowid {order} \
+                LIMIT ?1 OFFSET ?2"
+            ))?;
+            let posts = stmt
+                .query_map(params![page_size, page * page_size], post_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(posts)
+        })
+        .await
+    }
+
+    async fn update_post(&self, id: SmolStr, patch: PostRecordOption) -> anyhow::Result<()> {
+        // 暂时不允许更改文章id
+        let PostRecordOption {
+            title,
+            content,
+            created_time,
+            id: _,
+            draft,
+            pinned,
+            tags,
+            slug,
+        } = patch;
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let Some(mut post) = tx
+                .query_row(
+                    "SELECT * FROM post WHERE id = ?1",
+                    [id.as_str()],
+                    post_from_row,
+                )
+                .optional()?
+            else {
+                return Ok(());
+            };
+            macro_rules! merge {
+                ($($field:ident),*) => {
+                    $(if let Some(value) = $field {
+                        post.$field = value;
+                    })*
+                };
+            }
+            merge!(title, content, created_time, draft, pinned, tags, slug);
+            check_post(&post.title, &post.content, &post.tags, post.slug.as_deref())?;
+            tx.execute(
+                "UPDATE post SET title = ?2, content = ?3, created_time = ?4, draft = ?5, \
+                pinned = ?6, tags = ?7, slug = ?8 WHERE id = ?1",
+                params![
+                    id.as_str(),
+                    post.title.as_str(),
+                    post.content.as_str(),
+                    time_text(&post.created_time),
+                    post.draft,
+                    post.pinned,
+                    sonic_rs::to_string(&post.tags)?,
+                    post.slug.as_deref()
+                ],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_post(&self, id: SmolStr) -> anyhow::Result<()> {
+        self.call(move |conn| {
+            conn.execute("DELETE FROM post WHERE id = ?1", [id.as_str()])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_media(&self, media: NewMedia) -> anyhow::Result<(MediaRecord, bool)> {
+        check_media(&media)?;
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+            let existing = tx
+                .query_row(
+                    "SELECT * FROM media WHERE hash = ?1",
+                    [media.hash.as_str()],
+                    media_from_row,
+                )
+                .optional()?;
+            if let Some(existing) = existing {
+                return Ok((existing, false));
+            }
+            let created = tx.query_row(
+                "INSERT INTO media (id, filename, mime, size, width, height, hash, created_time) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) RETURNING *",
+                params![
+                    nanoid(8).as_str(),
+                    media.filename.as_str(),
+                    media.mime.as_str(),
+                    media.size,
+                    media.width,
+                    media.height,
+                    media.hash.as_str(),
+                    now_text()
+                ],
+                media_from_row,
+            )?;
+            tx.commit()?;
+            Ok((created, true))
+        })
+        .await
+    }
+
+    async fn query_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT * FROM media WHERE id = ?1",
+                    [id.as_str()],
+                    media_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn query_media_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+    ) -> anyhow::Result<Vec<MediaRecord>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT * FROM media ORDER BY created_time DESC, rowid DESC LIMIT ?1 OFFSET ?2",
+            )?;
+            let media = stmt
+                .query_map(params![page_size, page * page_size], media_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(media)
+        })
+        .await
+    }
+
+    async fn delete_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        self.call(move |conn| {
+            Ok(conn
+                .query_row(
+                    "DELETE FROM media WHERE id = ?1 RETURNING *",
+                    [id.as_str()],
+                    media_from_row,
+                )
+                .optional()?)
+        })
+        .await
+    }
+
+    async fn create_comment(&self, comment: NewComment) -> anyhow::Result<SmolStr> {
+        check_comment(&comment)?;
+        self.call(move |conn| {
+            let id = nanoid(8);
+            conn.execute(
+                "INSERT INTO comment (id, post, parent, author, email, url, content, created_time, approved) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    id.as_str(),
+                    comment.post.as_str(),
+                    comment.parent.as_deref(),
+                    comment.author.as_str(),
+                    comment.email.as_str(),
+                    comment.url.as_str(),
+                    comment.content.as_str(),
+                    comment
+                        .created_time
+                        .as_ref()
+                        .map_or_else(now_text, time_text),
+                    comment.approved
+                ],
+            )?;
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn query_comments_by_post(&self, post: SmolStr) -> anyhow::Result<Vec<CommentRecord>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT * FROM comment WHERE post = ?1 ORDER BY created_time ASC, rowid ASC",
+            )?;
+            let comments = stmt
+                .query_map([post.as_str()], comment_from_row)?
+                .collect::<Result<_, _>>()?;
+            Ok(comments)
+        })
+        .await
+    }
+}
@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use smol_str::SmolStr;
+use surrealdb::{Surreal, engine::any::Any};
+
+use super::Repository;
+use crate::db::{
+    connection::is_healthy,
+    model::{
+        comment::{self, CommentRecord, NewComment},
+        config::{self, ConfigRecord, ConfigRecordOption},
+        media::{self, MediaRecord, NewMedia},
+        post::{self, NewPost, PostRecord, PostRecordOption},
+        session,
+    },
+};
+
+#[async_trait]
+impl Repository for Surreal<Any> {
+    fn surreal(&self) -> Option<&Surreal<Any>> {
+        Some(self)
+    }
+
+    async fn health(&self) -> bool {
+        is_healthy(self).await
+    }
+
+    async fn is_new_install(&self) -> anyhow::Result<bool> {
+        config::is_new_install(self).await
+    }
+
+    async fn create_config(&self, config: ConfigRecord) -> anyhow::Result<()> {
+        config::create_config(self, config).await
+    }
+
+    async fn query_config(&self) -> anyhow::Result<ConfigRecord> {
+        config::query_config(self).await
+    }
+
+    async fn update_config(&self, config: ConfigRecordOption) -> anyhow::Result<()> {
+        config::update_config(self, config).await
+    }
+
+    async fn update_password(&self, pwd: String) -> anyhow::Result<()> {
+        config::update_password(self, pwd).await
+    }
+
+    async fn verify_password(&self, pwd: String) -> anyhow::Result<bool> {
+        config::verify_password(self, pwd).await
+    }
+
+    async fn session_secret(&self) -> anyhow::Result<String> {
+        session::session_secret(self).await
+    }
+
+    async fn query_session_epoch(&self) -> anyhow::Result<u64> {
+        session::query_session_epoch(self).await
+    }
+
+    async fn bump_session_epoch(&self) -> anyhow::Result<u64> {
+        session::bump_session_epoch(self).await
+    }
+
+    async fn insert_post(&self, post: NewPost) -> anyhow::Result<SmolStr> {
+        post::insert_post(self, post).await
+    }
+
+    async fn query_post(&self, id: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        post::query_post(self, id).await
+    }
+
+    async fn query_post_by_slug(&self, slug: SmolStr) -> anyhow::Result<Option<PostRecord>> {
+        post::query_post_by_slug(self, slug).await
+    }
+
+    async fn query_all_posts(&self) -> anyhow::Result<Vec<PostRecord>> {
+        post::query_all_posts(self).await
+    }
+
+    async fn query_posts_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+        asc: bool,
+    ) -> anyhow::Result<Vec<PostRecord>> {
+        post::query_posts_by_page(self, page, page_size, asc).await
+    }
+
+    async fn update_post(&self, id: SmolStr, post: PostRecordOption) -> anyhow::Result<()> {
+        post::update_post(self, id, post).await
+    }
+
+    async fn delete_post(&self, id: SmolStr) -> anyhow::Result<()> {
+        post::delete_post(self, id).await
+    }
+
+    async fn create_media(&self, media: NewMedia) -> anyhow::Result<(MediaRecord, bool)> {
+        media::create_media(self, media).await
+    }
+
+    async fn query_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        media::query_media(self, id).await
+    }
+
+    async fn query_media_by_page(
+        &self,
+        page: usize,
+        page_size: usize,
+    ) -> anyhow::Result<Vec<MediaRecord>> {
+        media::query_media_by_page(self, page, page_size).await
+    }
+
+    async fn delete_media(&self, id: SmolStr) -> anyhow::Result<Option<MediaRecord>> {
+        media::delete_media(self, id).await
+    }
+
+    async fn create_comment(&self, comment: NewComment) -> anyhow::Result<SmolStr> {
+        comment::create_comment(self, comment).await
+    }
+
+    async fn query_comments_by_post(&self, post: SmolStr) -> anyhow::Result<Vec<CommentRecord>> {
+        comment::query_comments_by_post(self, post).await
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::Context;
+use surrealdb::{Surreal, engine::any::Any};
+
+use crate::db::check_transaction;
+
+/// 当前数据库结构的版本, 每新增一个迁移就加一
+///
+/// 只描述SurrealDB的表和字段定义, 记录在不会被导出的`meta`表中,
+/// 站点设置的内容由[`SETTINGS_VERSION`](crate::db::model::config::SETTINGS_VERSION)单独迁移
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+/// 数据库结构的迁移脚本, 第`n`个脚本把结构从版本`n`升级到`n + 1`,
+/// 新增字段时追加一个脚本定义字段并补全旧记录, 不要修改已经发布的脚本
+const MIGRATIONS: &[&str] = &[
+    include_str!("migrations/0001_initial.surql"),
+    include_str!("migrations/0002_schemafull.surql"),
+];
+
+/// 记录数据库结构版本的表, 导出时不包含这张表
+pub const META_TABLE: &str = "meta";
+
+pub async fn schema_version(db: &Surreal<Any>) -> anyhow::Result<usize> {
+    Ok(db
+        .query("RETURN (SELECT version FROM ONLY meta:schema).version")
+        .await?
+        .take::<Option<usize>>(0)?
+        .unwrap_or_default())
+}
+
+/// 按顺序执行未执行过的迁移, 遇到更新版本程序创建的数据库时拒绝启动
+pub async fn migrate_schema(db: &Surreal<Any>) -> anyhow::Result<()> {
+    let version = schema_version(db).await?;
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "database schema version {version} is newer than supported version {SCHEMA_VERSION}"
+        );
+    }
+
+    for (from, script) in MIGRATIONS.iter().enumerate().skip(version) {
+        tracing::info!(
+            "migrating database schema from version {from} to {}",
+            from + 1
+        );
+        let mut resp = db
+            .query("BEGIN TRANSACTION;")
+            .query(*script)
+            .query("UPSERT meta:schema SET version = $version, migrated_time = time::now();")
+            .query("COMMIT TRANSACTION;")
+            .bind(("version", from + 1))
+            .await?;
+        check_transaction(&mut resp).with_context(|| format!("migration {} failed", from + 1))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::model::post::query_post;
+
+    use super::{SCHEMA_VERSION, migrate_schema, schema_version};
+
+    #[tokio::test]
+    async fn test_migrate_schema() -> anyhow::Result<()> {
+        let db = surrealdb::engine::any::connect("mem://").await?;
+        db.use_ns("bulog").use_db("blog").await?;
+        // 没有定义结构时写入的旧文章
+        db.query("CREATE post:old SET title = 'old', content = '', created_time = time::now()")
+            .await?
+            .check()?;
+        assert_eq!(schema_version(&db).await?, 0);
+
+        migrate_schema(&db).await?;
+        assert_eq!(schema_version(&db).await?, SCHEMA_VERSION);
+        let post = query_post(&db, "old".into()).await?.unwrap();
+        assert!(post.tags.is_empty() && !post.draft && !post.pinned);
+        // 再次执行不会重复迁移
+        migrate_schema(&db).await?;
+
+        assert!(
+            db.query("CREATE post SET title = 1, content = ''")
+                .await?
+                .check()
+                .is_err()
+        );
+
+        db.query("UPDATE meta:schema SET version = $v")
+            .bind(("v", SCHEMA_VERSION + 1))
+            .await?
+            .check()?;
+        assert!(migrate_schema(&db).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Deserialize;
+use smol_str::SmolStr;
+
+use super::{ImportReport, Outcome, import_post};
+use crate::db::{model::post::NewPost, repo::Repository};
+
+/// Hugo的分区页面, 不是文章
+const SECTION_INDEX: &str = "_index.md";
+
+/// Jekyll和Hexo存放草稿的目录
+const DRAFTS_DIR: &str = "_drafts";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FrontMatter {
+    title: Option<String>,
+    date: Option<String>,
+    draft: bool,
+    /// Jekyll和Hexo用`published: false`表示草稿
+    published: Option<bool>,
+    tags: Option<Tags>,
+    slug: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Tags {
+    /// Jekyll允许用空格分隔的字符串
+    One(String),
+    Many(Vec<String>),
+}
+
+/// 导入`dir`中所有的Markdown文件, slug取自front matter或文件名
+pub async fn import_markdown(
+    repo: &dyn Repository,
+    dir: &Path,
+    dry_run: bool,
+) -> anyhow::Result<ImportReport> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files).with_context(|| format!("failed to read {}", dir.display()))?;
+    files.sort();
+
+    let mut report = ImportReport::default();
+    let mut slugs = HashSet::new();
+    for path in files {
+        let source = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        if path.file_name().is_some_and(|name| name == SECTION_INDEX) {
+            report.push(source, Outcome::Skipped("section index".to_owned()));
+            continue;
+        }
+        let post = match std::fs::read_to_string(&path)
+            .map_err(Into::into)
+            .and_then(|text| parse_post(&path, &text))
+        {
+            Ok(post) => post,
+            Err(err) => {
+                report.push(source, Outcome::Failed(format!("{err:#}")));
+                continue;
+            }
+        };
+        let outcome = import_post(repo, &mut slugs, post, dry_run).await;
+        report.push(source, outcome);
+    }
+    Ok(report)
+}
+
+/// 递归查找`.md`和`.markdown`文件, 跳过隐藏目录
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "md" || ext == "markdown")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// 缺少的字段从文件名推断: Jekyll的`2024-01-02-slug.md`, Hugo的`slug/index.md`
+fn parse_post(path: &Path, text: &str) -> anyhow::Result<NewPost> {
+    let (front_matter, body) = split_front_matter(text)?;
+
+    let mut stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if stem == "index"
+        && let Some(parent) = path.parent().and_then(Path::file_name)
+    {
+        stem = parent.to_string_lossy().into_owned();
+    }
+    let (file_date, file_slug) = match stem.get(..10).map(|date| (date, &stem[10..])) {
+        Some((date, rest)) if rest.starts_with('-') => {
+            match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                Ok(date) => (Some(date), rest[1..].to_owned()),
+                Err(_) => (None, stem.clone()),
+            }
+        }
+        _ => (None, stem.clone()),
+    };
+
+    let (title, content) = match front_matter.title {
+        Some(title) => (title, SmolStr::from(body.trim_start())),
+        None => {
+            let (title, content) = split_title(body);
+            (title.unwrap_or_else(|| file_slug.clone()), content)
+        }
+    };
+    let created_time = match (&front_matter.date, file_date) {
+        (Some(date), _) => Some(parse_date(date)?),
+        (None, Some(date)) => Some(date.and_time(Default::default()).and_utc()),
+        (None, None) => None,
+    };
+    let tags = match front_matter.tags {
+        Some(Tags::One(tags)) if tags.contains(',') => {
+            tags.split(',').map(str::trim).map(Into::into).collect()
+        }
+        Some(Tags::One(tags)) => tags.split_whitespace().map(Into::into).collect(),
+        Some(Tags::Many(tags)) => tags.iter().map(|tag| tag.trim().into()).collect(),
+        None => Vec::<SmolStr>::new(),
+    }
+    .into_iter()
+    .filter(|tag| !tag.is_empty())
+    .collect();
+    let in_drafts = path.components().any(|part| part.as_os_str() == DRAFTS_DIR);
+
+    Ok(NewPost {
+        title: title.into(),
+        content,
+        created_time: created_time.map(Into::into),
+        draft: front_matter.draft || front_matter.published == Some(false) || in_drafts,
+        pinned: false,
+        tags,
+        slug: Some(front_matter.slug.unwrap_or(file_slug).into()),
+    })
+}
+
+/// `---`包围的是YAML, `+++`包围的是TOML, 没有front matter时返回默认值
+fn split_front_matter(text: &str) -> anyhow::Result<(FrontMatter, &str)> {
+    let text = text.trim_start_matches('\u{feff}');
+    let Some((first, rest)) = text.split_once('\n') else {
+        return Ok((FrontMatter::default(), text));
+    };
+    let fence = first.trim_end();
+    if fence != "---" && fence != "+++" {
+        return Ok((FrontMatter::default(), text));
+    }
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == fence {
+            let raw = &rest[..offset];
+            let body = &rest[offset + line.len()..];
+            if raw.trim().is_empty() {
+                return Ok((FrontMatter::default(), body));
+            }
+            let front_matter = if fence == "---" {
+                serde_yaml::from_str(raw).context("invalid YAML front matter")?
+            } else {
+                parse_toml(raw).context("invalid TOML front matter")?
+            };
+            return Ok((front_matter, body));
+        }
+        offset += line.len();
+    }
+    anyhow::bail!("front matter is not closed")
+}
+
+fn parse_toml(raw: &str) -> anyhow::Result<FrontMatter> {
+    let mut table: toml::Table = toml::from_str(raw)?;
+    // TOML的日期是单独的类型, 转换为字符串后和YAML使用同样的解析方式
+    if let Some(toml::Value::Datetime(date)) = table.get("date") {
+        let date = date.to_string();
+        table.insert("date".to_owned(), toml::Value::String(date));
+    }
+    Ok(table.try_into()?)
+}
+
+/// 没有时区的时间按UTC处理
+fn parse_date(date: &str) -> anyhow::Result<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.to_utc());
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S %z",
+        "%Y-%m-%d %H:%M:%S%.f%:z",
+        "%Y-%m-%d %H:%M %z",
+    ] {
+        if let Ok(date) = DateTime::parse_from_str(date, format) {
+            return Ok(date.to_utc());
+        }
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(date, format) {
+            return Ok(date.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| date.and_time(Default::default()).and_utc())
+        .with_context(|| format!("unrecognized date {date}"))
+}
+
+/// 第一个非空行是`# 标题`时把它拆出来, 其余部分作为正文
+pub fn split_title(markdown: &str) -> (Option<String>, SmolStr) {
+    let trimmed = markdown.trim_start();
+    if let Some(rest) = trimmed.strip_prefix("# ") {
+        let (title, content) = rest.split_once('\n').unwrap_or((rest, ""));
+        return (Some(title.trim().to_owned()), content.trim_start().into());
+    }
+    (None, markdown.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        db::{model::post::query_post, test_db},
+        storage::TempDir,
+    };
+
+    use super::{Outcome, import_markdown, parse_post, split_title};
+
+    #[test]
+    fn test_split_title() {
+        let (title, content) = split_title("\n# Hello world\n\nbody\n");
+        assert_eq!(title.as_deref(), Some("Hello world"));
+        assert_eq!(content, "body\n");
+
+        let (title, content) = split_title("## not a title\nbody");
+        assert_eq!(title, None);
+        assert_eq!(content, "## not a title\nbody");
+    }
+
+    #[test]
+    fn test_parse_post() -> anyhow::Result<()> {
+        let hugo = parse_post(
+            Path::new("content/posts/hello/index.md"),
+            "+++\ntitle = \"Hello\"\ndate = 2023-05-01T10:00:00+08:00\ntags = [\"a\", \"b\"]\n+++\n\nbody\n",
+        )?;
+        assert_eq!(hugo.title, "Hello");
+        assert_eq!(hugo.content, "body\n");
+        assert_eq!(hugo.slug.as_deref(), Some("hello"));
+        assert_eq!(hugo.tags, ["a", "b"]);
+        assert_eq!(
+            hugo.created_time.map(|time| time.to_string()).as_deref(),
+            Some("d'2023-05-01T02:00:00Z'")
+        );
+
+        let jekyll = parse_post(
+            Path::new("_posts/2022-03-04-first-post.md"),
+            "---\nlayout: post\ntags: rust web\npublished: false\n---\n# First\ncontent",
+        )?;
+        assert_eq!(jekyll.title, "First");
+        assert_eq!(jekyll.content, "content");
+        assert_eq!(jekyll.slug.as_deref(), Some("first-post"));
+        assert_eq!(jekyll.tags, ["rust", "web"]);
+        assert!(jekyll.draft);
+        assert_eq!(
+            jekyll.created_time.map(|time| time.to_string()).as_deref(),
+            Some("d'2022-03-04T00:00:00Z'")
+        );
+
+        let hexo = parse_post(
+            Path::new("source/_drafts/note.md"),
+            "---\ntitle: Note\ndate: 2021-01-02 03:04:05\nslug: my-note\n---\ntext",
+        )?;
+        assert_eq!(hexo.slug.as_deref(), Some("my-note"));
+        assert!(hexo.draft);
+        assert_eq!(
+            hexo.created_time.map(|time| time.to_string()).as_deref(),
+            Some("d'2021-01-02T03:04:05Z'")
+        );
+
+        assert!(parse_post(Path::new("a.md"), "---\ntitle: a\n").is_err());
+        assert!(parse_post(Path::new("a.md"), "---\ndate: yesterday\n---\n").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_markdown() -> anyhow::Result<()> {
+        let db = test_db().await?;
+        let dir = TempDir::new("bulog-markdown");
+        std::fs::create_dir_all(dir.join("posts"))?;
+        std::fs::write(
+            dir.join("posts/2020-01-01-a.md"),
+            "---\ntitle: A\n---\nfirst",
+        )?;
+        std::fs::write(dir.join("posts/b.md"), "---\ntitle: [broken\n---\n")?;
+        std::fs::write(dir.join("posts/_index.md"), "---\ntitle: Posts\n---\n")?;
+        std::fs::write(dir.join("notes.txt"), "not markdown")?;
+
+        let report = import_markdown(&db, &dir, true).await?;
+        assert_eq!(
+            (report.imported(), report.skipped(), report.failed()),
+            (1, 1, 1)
+        );
+        assert!(
+            crate::db::model::post::query_all_posts(&db)
+                .await?
+                .is_empty()
+        );
+
+        let report = import_markdown(&db, &dir, false).await?;
+        let Some((_, Outcome::Imported(Some(id)))) = report.entries.first() else {
+            panic!("unexpected report {report:?}");
+        };
+        let post = query_post(&db, id.clone()).await?.unwrap();
+        assert_eq!(post.title, "A");
+        assert_eq!(post.slug.as_deref(), Some("a"));
+        assert_eq!(post.created_time.to_string(), "d'2020-01-01T00:00:00Z'");
+
+        // 重复导入时跳过已有的文章
+        let report = import_markdown(&db, &dir, false).await?;
+        assert_eq!((report.imported(), report.skipped()), (0, 2));
+        Ok(())
+    }
+}
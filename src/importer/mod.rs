@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+
+use crate::db::{model::post::NewPost, repo::Repository};
+use smol_str::SmolStr;
+
+pub mod markdown;
+pub mod wordpress;
+
+/// 单个文件或条目的导入结果
+#[derive(Debug)]
+pub enum Outcome {
+    /// 导入后的文章id, 试运行时为空
+    Imported(Option<SmolStr>),
+    Skipped(String),
+    Failed(String),
+}
+
+/// 每个来源的导入结果, 单个来源失败不影响其他来源的导入
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub entries: Vec<(String, Outcome)>,
+}
+
+impl ImportReport {
+    pub fn push(&mut self, source: impl Into<String>, outcome: Outcome) {
+        self.entries.push((source.into(), outcome));
+    }
+
+    pub fn imported(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Imported(_)))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Skipped(_)))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    }
+
+    fn count(&self, f: impl Fn(&Outcome) -> bool) -> usize {
+        self.entries
+            .iter()
+            .filter(|(_, outcome)| f(outcome))
+            .count()
+    }
+}
+
+/// 已经存在相同slug的文章时跳过, `slugs`记录本次导入中出现过的slug, 重复导入时不会产生重复的文章
+async fn import_post(
+    repo: &dyn Repository,
+    slugs: &mut HashSet<SmolStr>,
+    post: NewPost,
+    dry_run: bool,
+) -> Outcome {
+    let slug = post.slug.clone().unwrap_or_default();
+    if !slugs.insert(slug.clone()) {
+        return Outcome::Skipped(format!("duplicate slug {slug}"));
+    }
+    match repo.query_post_by_slug(slug.clone()).await {
+        Ok(Some(existing)) => Outcome::Skipped(format!(
+            "slug {slug} already exists as post {}",
+            existing.id
+        )),
+        Ok(None) if dry_run => Outcome::Imported(None),
+        Ok(None) => match repo.insert_post(post).await {
+            Ok(id) => Outcome::Imported(Some(id)),
+            Err(err) => Outcome::Failed(format!("{err:#}")),
+        },
+        Err(err) => Outcome::Failed(format!("{err:#}")),
+    }
+}
@@ -0,0 +1,599 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use percent_encoding::percent_decode_str;
+use roxmltree::Node;
+use smol_str::SmolStr;
+use url::Url;
+
+use super::{ImportReport, Outcome, import_post};
+use crate::{
+    db::{
+        model::{
+            comment::NewComment,
+            media::{file_url, store_media},
+            post::NewPost,
+        },
+        repo::Repository,
+    },
+    storage::DynStorage,
+};
+
+/// WXR各版本的命名空间都以此开头, 例如`http://wordpress.org/export/1.2/`
+const WP_NS: &str = "http://wordpress.org/export/";
+const CONTENT_NS: &str = "http://purl.org/rss/1.0/modules/content/";
+
+/// 附件地址中这个路径之后的部分对应本地上传目录中的文件
+const UPLOADS_PATH: &str = "/wp-content/uploads/";
+
+/// 导入的页面带有这个标签, 与普通文章区分
+const PAGE_TAG: &str = "page";
+
+pub struct WordpressOptions<'a> {
+    /// 本地的`wp-content/uploads`目录, 为空时不导入附件, 文章中的图片仍然指向原来的地址
+    pub uploads: Option<&'a Path>,
+    /// 把页面作为文章导入
+    pub pages: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default)]
+struct Item {
+    post_id: String,
+    post_type: String,
+    status: String,
+    title: String,
+    slug: String,
+    content: String,
+    date: Option<DateTime<Utc>>,
+    sticky: bool,
+    tags: Vec<SmolStr>,
+    attachment_url: Option<String>,
+    comments: Vec<Comment>,
+}
+
+#[derive(Debug, Default)]
+struct Comment {
+    id: String,
+    parent: String,
+    author: String,
+    email: String,
+    url: String,
+    content: String,
+    date: Option<DateTime<Utc>>,
+    approved: String,
+    kind: String,
+}
+
+/// 导入WordPress导出的WXR文件, 先导入附件, 再导入文章和评论
+pub async fn import_wordpress(
+    repo: &dyn Repository,
+    storage: &DynStorage,
+    file: &Path,
+    options: WordpressOptions<'_>,
+) -> anyhow::Result<ImportReport> {
+    let xml = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let items = parse_wxr(&xml)?;
+
+    let mut report = ImportReport::default();
+    // 原地址和导入后地址的对应关系, 用于替换文章中的链接
+    let mut urls = Vec::new();
+    for item in items.iter().filter(|item| item.post_type == "attachment") {
+        let Some(url) = &item.attachment_url else {
+            continue;
+        };
+        let (outcome, media) = import_attachment(repo, storage, url, &options).await;
+        if let Some(media) = media {
+            urls.push((url.clone(), file_url(&media)));
+        }
+        report.push(format!("attachment {url}"), outcome);
+    }
+
+    let mut slugs = HashSet::new();
+    for item in items
+        .into_iter()
+        .filter(|item| item.post_type != "attachment")
+    {
+        let source = format!("{} {}", item.post_type, item.slug_or_id());
+        match item.post_type.as_str() {
+            "post" => {}
+            "page" if options.pages => {}
+            "page" => {
+                report.push(
+                    source,
+                    Outcome::Skipped("pages are not imported".to_owned()),
+                );
+                continue;
+            }
+            _ => {
+                report.push(source, Outcome::Skipped("unsupported post type".to_owned()));
+                continue;
+            }
+        }
+        if item.status == "trash" {
+            report.push(source, Outcome::Skipped("post is in trash".to_owned()));
+            continue;
+        }
+
+        let mut content = item.content.clone();
+        for (from, to) in &urls {
+            content = content.replace(from, to);
+        }
+        let mut tags = item.tags.clone();
+        if item.post_type == "page" {
+            tags.push(PAGE_TAG.into());
+        }
+        let post = NewPost {
+            title: if item.title.is_empty() {
+                item.slug_or_id().into()
+            } else {
+                item.title.as_str().into()
+            },
+            content: html_to_markdown(&content).into(),
+            created_time: item.date.map(Into::into),
+            draft: item.status != "publish",
+            pinned: item.sticky,
+            tags,
+            slug: Some(item.slug_or_id().into()),
+        };
+        let outcome = import_post(repo, &mut slugs, post, options.dry_run).await;
+        let post_id = match &outcome {
+            Outcome::Imported(id) => Some(id.clone()),
+            _ => None,
+        };
+        report.push(&source, outcome);
+        // 文章被跳过时不导入评论, 重复导入时不会产生重复的评论
+        if let Some(post_id) = post_id {
+            import_comments(repo, &mut report, &source, post_id, item.comments).await;
+        }
+    }
+    Ok(report)
+}
+
+/// 同时返回导入后的媒体id, 文件已经存在时也会返回
+async fn import_attachment(
+    repo: &dyn Repository,
+    storage: &DynStorage,
+    url: &str,
+    options: &WordpressOptions<'_>,
+) -> (Outcome, Option<SmolStr>) {
+    let Some(uploads) = options.uploads else {
+        return (
+            Outcome::Skipped("uploads directory is not given".to_owned()),
+            None,
+        );
+    };
+    let path = match attachment_path(uploads, url) {
+        Ok(path) => path,
+        Err(outcome) => return (outcome, None),
+    };
+    if options.dry_run {
+        return (Outcome::Imported(None), None);
+    }
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+    let stored = match std::fs::read(&path) {
+        Ok(data) => store_media(repo, storage, filename.into(), mime, data).await,
+        Err(err) => Err(err.into()),
+    };
+    match stored {
+        Ok((media, true)) => (Outcome::Imported(Some(media.id.clone())), Some(media.id)),
+        Ok((media, false)) => (
+            Outcome::Skipped(format!("same file already exists as media {}", media.id)),
+            Some(media.id),
+        ),
+        Err(err) => (Outcome::Failed(format!("{err:#}")), None),
+    }
+}
+
+/// 附件地址对应的本地文件, 地址来自导出文件, 不能信任, 只接受上传目录中的文件
+fn attachment_path(uploads: &Path, url: &str) -> Result<PathBuf, Outcome> {
+    let url = Url::parse(url).map_err(|err| Outcome::Skipped(format!("invalid url: {err}")))?;
+    let Some((_, relative)) = url.path().split_once(UPLOADS_PATH) else {
+        return Err(Outcome::Skipped("not in uploads directory".to_owned()));
+    };
+    let relative = percent_decode_str(relative)
+        .decode_utf8()
+        .map_err(|_| Outcome::Skipped("file name is not utf-8".to_owned()))?;
+    let relative = Path::new(&*relative);
+    let escapes = || {
+        Outcome::Failed(format!(
+            "{} is outside the uploads directory",
+            relative.display()
+        ))
+    };
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(escapes());
+    }
+    let not_found = || Outcome::Skipped(format!("{} not found", uploads.join(relative).display()));
+    let uploads = uploads.canonicalize().map_err(|_| not_found())?;
+    let path = uploads
+        .join(relative)
+        .canonicalize()
+        .map_err(|_| not_found())?;
+    // 上传目录中的符号链接也可能指向外面
+    if !path.starts_with(&uploads) {
+        return Err(escapes());
+    }
+    if !path.is_file() {
+        return Err(not_found());
+    }
+    Ok(path)
+}
+
+/// `post_id`为空表示试运行
+async fn import_comments(
+    repo: &dyn Repository,
+    report: &mut ImportReport,
+    post_source: &str,
+    post_id: Option<SmolStr>,
+    mut comments: Vec<Comment>,
+) {
+    // 按id排序保证回复的评论已经导入
+    comments.sort_by_key(|comment| comment.id.parse::<u64>().unwrap_or(u64::MAX));
+    let mut ids = HashMap::new();
+    for comment in comments {
+        let source = format!("comment {} on {post_source}", comment.id);
+        if matches!(comment.approved.as_str(), "spam" | "trash") {
+            report.push(
+                source,
+                Outcome::Skipped(format!("comment is {}", comment.approved)),
+            );
+            continue;
+        }
+        if matches!(comment.kind.as_str(), "pingback" | "trackback") {
+            report.push(source, Outcome::Skipped(comment.kind));
+            continue;
+        }
+        let Some(post) = &post_id else {
+            report.push(source, Outcome::Imported(None));
+            continue;
+        };
+        let outcome = repo
+            .create_comment(NewComment {
+                post: post.clone(),
+                parent: ids.get(&comment.parent).cloned(),
+                author: comment.author.into(),
+                email: comment.email.into(),
+                url: comment.url.into(),
+                content: html_to_markdown(&comment.content).into(),
+                created_time: comment.date.map(Into::into),
+                approved: comment.approved == "1",
+            })
+            .await;
+        match outcome {
+            Ok(id) => {
+                ids.insert(comment.id, id.clone());
+                report.push(source, Outcome::Imported(Some(id)));
+            }
+            Err(err) => report.push(source, Outcome::Failed(format!("{err:#}"))),
+        }
+    }
+}
+
+impl Item {
+    /// 草稿可能没有slug
+    fn slug_or_id(&self) -> &str {
+        if self.slug.is_empty() {
+            &self.post_id
+        } else {
+            &self.slug
+        }
+    }
+}
+
+fn parse_wxr(xml: &str) -> anyhow::Result<Vec<Item>> {
+    let doc = roxmltree::Document::parse(xml).context("invalid WXR file")?;
+    let channel = doc
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("channel"))
+        .context("channel element is missing")?;
+
+    let mut items = Vec::new();
+    for node in channel.children().filter(|node| node.has_tag_name("item")) {
+        let mut item = Item::default();
+        for child in node.children().filter(Node::is_element) {
+            let text = || child.text().unwrap_or_default().to_owned();
+            match (is_wp(child), child.tag_name().name()) {
+                (false, "title") => item.title = text(),
+                (false, "encoded") if child.tag_name().namespace() == Some(CONTENT_NS) => {
+                    item.content = text()
+                }
+                (false, "category") => {
+                    let domain = child.attribute("domain").unwrap_or_default();
+                    let nicename = child.attribute("nicename").unwrap_or_default();
+                    // 默认分类没有意义
+                    if matches!(domain, "category" | "post_tag") && nicename != "uncategorized" {
+                        let tag = SmolStr::from(text().trim());
+                        if !tag.is_empty() && !item.tags.contains(&tag) {
+                            item.tags.push(tag);
+                        }
+                    }
+                }
+                (true, "post_id") => item.post_id = text(),
+                (true, "post_type") => item.post_type = text(),
+                (true, "status") => item.status = text(),
+                (true, "post_name") => item.slug = text(),
+                (true, "is_sticky") => item.sticky = text() == "1",
+                (true, "attachment_url") => item.attachment_url = Some(text()),
+                (true, "post_date_gmt") => item.date = parse_date(&text()).or(item.date),
+                // 草稿的GMT时间为空, 使用站点时区的时间
+                (true, "post_date") => item.date = item.date.or(parse_date(&text())),
+                (true, "comment") => item.comments.push(parse_comment(child)),
+                _ => {}
+            }
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+fn parse_comment(node: Node) -> Comment {
+    let mut comment = Comment::default();
+    for child in node.children().filter(|child| is_wp(*child)) {
+        let text = child.text().unwrap_or_default().to_owned();
+        match child.tag_name().name() {
+            "comment_id" => comment.id = text,
+            "comment_parent" => comment.parent = text,
+            "comment_author" => comment.author = text,
+            "comment_author_email" => comment.email = text,
+            "comment_author_url" => comment.url = text,
+            "comment_content" => comment.content = text,
+            "comment_date_gmt" => comment.date = parse_date(&text).or(comment.date),
+            "comment_date" => comment.date = comment.date.or(parse_date(&text)),
+            "comment_approved" => comment.approved = text,
+            "comment_type" => comment.kind = text,
+            _ => {}
+        }
+    }
+    comment
+}
+
+fn is_wp(node: Node) -> bool {
+    node.tag_name()
+        .namespace()
+        .is_some_and(|ns| ns.starts_with(WP_NS))
+}
+
+/// WXR中的时间格式为`2024-01-02 03:04:05`, 未设置时为`0000-00-00 00:00:00`
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(date.trim(), "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+/// 经典编辑器保存的内容用空行分隔段落, 渲染时才由WordPress补上`<p>`
+fn html_to_markdown(html: &str) -> String {
+    let html = if html.contains("<p") {
+        html.to_owned()
+    } else {
+        html.split("\n\n")
+            .map(str::trim)
+            .filter(|block| !block.is_empty())
+            .map(|block| {
+                if is_block_element(block) {
+                    block.to_owned()
+                } else {
+                    format!("<p>{block}</p>")
+                }
+            })
+            .collect()
+    };
+    // 无法转换时保留原始的HTML, Markdown中可以直接使用HTML
+    htmd::convert(&html).unwrap_or(html)
+}
+
+fn is_block_element(block: &str) -> bool {
+    [
+        "<ul",
+        "<ol",
+        "<pre",
+        "<blockquote",
+        "<table",
+        "<div",
+        "<figure",
+        "<h1",
+        "<h2",
+        "<h3",
+        "<h4",
+        "<h5",
+        "<h6",
+    ]
+    .iter()
+    .any(|tag| block.starts_with(tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        db::{
+            model::{comment::query_comments_by_post, post::query_all_posts},
+            test_db,
+        },
+        storage::{DynStorage, MemoryStorage, TempDir},
+    };
+
+    use super::{Outcome, WordpressOptions, attachment_path, html_to_markdown, import_wordpress};
+
+    const WXR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"
+    xmlns:content="http://purl.org/rss/1.0/modules/content/"
+    xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+    <item>
+        <title>Photo</title>
+        <wp:post_id>10</wp:post_id>
+        <wp:post_type><![CDATA[attachment]]></wp:post_type>
+        <wp:status><![CDATA[inherit]]></wp:status>
+        <wp:attachment_url><![CDATA[https://example.com/wp-content/uploads/2020/01/photo.png]]></wp:attachment_url>
+    </item>
+    <item>
+        <title>Hello World</title>
+        <content:encoded><![CDATA[First <strong>paragraph</strong>.
+
+<img src="https://example.com/wp-content/uploads/2020/01/photo.png" alt="photo">]]></content:encoded>
+        <wp:post_id>1</wp:post_id>
+        <wp:post_date><![CDATA[2020-01-02 11:04:05]]></wp:post_date>
+        <wp:post_date_gmt><![CDATA[2020-01-02 03:04:05]]></wp:post_date_gmt>
+        <wp:post_name><![CDATA[hello-world]]></wp:post_name>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+        <wp:is_sticky>1</wp:is_sticky>
+        <category domain="category" nicename="uncategorized"><![CDATA[Uncategorized]]></category>
+        <category domain="category" nicename="news"><![CDATA[News]]></category>
+        <category domain="post_tag" nicename="rust"><![CDATA[rust]]></category>
+        <wp:comment>
+            <wp:comment_id>2</wp:comment_id>
+            <wp:comment_author><![CDATA[bob]]></wp:comment_author>
+            <wp:comment_date_gmt><![CDATA[2020-01-03 00:00:00]]></wp:comment_date_gmt>
+            <wp:comment_content><![CDATA[reply]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[0]]></wp:comment_approved>
+            <wp:comment_parent>1</wp:comment_parent>
+        </wp:comment>
+        <wp:comment>
+            <wp:comment_id>1</wp:comment_id>
+            <wp:comment_author><![CDATA[alice]]></wp:comment_author>
+            <wp:comment_date_gmt><![CDATA[2020-01-02 05:00:00]]></wp:comment_date_gmt>
+            <wp:comment_content><![CDATA[nice]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[1]]></wp:comment_approved>
+            <wp:comment_parent>0</wp:comment_parent>
+        </wp:comment>
+        <wp:comment>
+            <wp:comment_id>3</wp:comment_id>
+            <wp:comment_content><![CDATA[buy now]]></wp:comment_content>
+            <wp:comment_approved><![CDATA[spam]]></wp:comment_approved>
+        </wp:comment>
+    </item>
+    <item>
+        <title>About</title>
+        <content:encoded><![CDATA[about me]]></content:encoded>
+        <wp:post_id>2</wp:post_id>
+        <wp:post_date_gmt><![CDATA[0000-00-00 00:00:00]]></wp:post_date_gmt>
+        <wp:post_name><![CDATA[about]]></wp:post_name>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_type><![CDATA[page]]></wp:post_type>
+    </item>
+</channel>
+</rss>"#;
+
+    #[test]
+    fn test_html_to_markdown() {
+        assert_eq!(
+            html_to_markdown("one <em>a</em>\n\ntwo\n\n<ul><li>x</li></ul>"),
+            "one *a*\n\ntwo\n\n*   x"
+        );
+        assert_eq!(html_to_markdown("<p>a</p><p>b</p>"), "a\n\nb");
+    }
+
+    #[test]
+    fn test_attachment_path() -> anyhow::Result<()> {
+        let dir = TempDir::new("bulog-wxr-path");
+        let uploads = dir.join("uploads");
+        std::fs::create_dir_all(uploads.join("2020/01"))?;
+        std::fs::write(dir.join("secret.txt"), b"secret")?;
+        std::fs::write(uploads.join("2020/01/my photo.png"), b"png")?;
+        std::fs::write(uploads.join("2020/01/照片.png"), b"png")?;
+
+        let resolve = |url: &str| attachment_path(&uploads, url);
+        let base = "https://example.com/wp-content/uploads";
+        assert_eq!(
+            resolve(&format!("{base}/2020/01/my%20photo.png?ver=2"))
+                .map_err(|outcome| format!("{outcome:?}"))
+                .unwrap(),
+            uploads.canonicalize()?.join("2020/01/my photo.png")
+        );
+        assert!(resolve(&format!("{base}/2020/01/%E7%85%A7%E7%89%87.png")).is_ok());
+        // 地址中的`..`和`%2e%2e`在解析时就会被合并, 不再位于上传目录中
+        for url in [
+            format!("{base}/../secret.txt"),
+            format!("{base}/%2e%2e/secret.txt"),
+            format!("{base}/2020/01/../../../secret.txt"),
+        ] {
+            assert!(
+                matches!(resolve(&url), Err(Outcome::Skipped(_))),
+                "{url} is accepted"
+            );
+        }
+        for url in [
+            format!("{base}//etc/passwd"),
+            format!("{base}/%2Fetc/passwd"),
+            format!("{base}/2020/%2F..%2F..%2F..%2Fsecret.txt"),
+        ] {
+            assert!(
+                matches!(resolve(&url), Err(Outcome::Failed(_))),
+                "{url} is accepted"
+            );
+        }
+        assert!(matches!(
+            resolve(&format!("{base}/2020/01/missing.png")),
+            Err(Outcome::Skipped(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_wordpress() -> anyhow::Result<()> {
+        let db = test_db().await?;
+        let storage: DynStorage = Arc::new(MemoryStorage::default());
+        let dir = TempDir::new("bulog-wxr");
+        std::fs::create_dir_all(dir.join("uploads/2020/01"))?;
+        std::fs::write(dir.join("uploads/2020/01/photo.png"), b"not really a png")?;
+        std::fs::write(dir.join("export.xml"), WXR)?;
+        let uploads = dir.join("uploads");
+        let options = |dry_run| WordpressOptions {
+            uploads: Some(&uploads),
+            pages: false,
+            dry_run,
+        };
+
+        let report =
+            import_wordpress(&db, &storage, &dir.join("export.xml"), options(true)).await?;
+        assert_eq!((report.imported(), report.skipped()), (4, 2));
+        assert!(query_all_posts(&db).await?.is_empty());
+
+        let report =
+            import_wordpress(&db, &storage, &dir.join("export.xml"), options(false)).await?;
+        assert_eq!(report.failed(), 0);
+        let Some((_, Outcome::Imported(Some(media)))) = report.entries.first() else {
+            panic!("unexpected report {report:?}");
+        };
+        let posts = query_all_posts(&db).await?;
+        assert_eq!(posts.len(), 1);
+        let post = &posts[0];
+        assert_eq!(post.title, "Hello World");
+        assert_eq!(post.slug.as_deref(), Some("hello-world"));
+        assert_eq!(post.tags, ["News", "rust"]);
+        assert!(post.pinned && !post.draft);
+        assert_eq!(post.created_time.to_string(), "d'2020-01-02T03:04:05Z'");
+        assert!(post.content.starts_with("First **paragraph**."));
+        assert!(post.content.contains(&format!("/v1/media/{media}/file")));
+
+        let comments = query_comments_by_post(&db, post.id.clone()).await?;
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].author, "alice");
+        assert!(comments[0].approved);
+        assert_eq!(comments[1].parent.as_ref(), Some(&comments[0].id));
+        assert!(!comments[1].approved);
+
+        // 重复导入时文章和评论都不会重复
+        let report =
+            import_wordpress(&db, &storage, &dir.join("export.xml"), options(false)).await?;
+        assert_eq!(report.imported(), 0);
+        assert_eq!(query_comments_by_post(&db, post.id.clone()).await?.len(), 2);
+        Ok(())
+    }
+}
@@ -1,31 +1,71 @@
 use std::time::Duration;
 
+use clap::Parser;
+use cli::{Cli, Command};
+use config::ServerConfig;
 use salvo::server::ServerHandle;
 use tokio::signal;
 use web::web_server;
 
+mod cli;
+mod config;
 mod db;
+mod importer;
+mod metrics;
+mod site;
+mod storage;
+mod telemetry;
 mod web;
 
 mod nano_id;
+mod thumbnail;
 
 fn main() {
     dotenv::dotenv().ok();
-    tracing_subscriber::fmt()
-        .with_env_filter(std::env::var("BU_LOG").unwrap_or_else(|_| "warn,bulog=info".to_owned()))
-        .init();
-    tokio::runtime::Builder::new_multi_thread()
+    let cli = Cli::parse();
+    let config = match ServerConfig::load(&cli) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("invalid configuration: {err:#}");
+            std::process::exit(1);
+        }
+    };
+    if cli.print_config {
+        print!(
+            "{}",
+            toml::to_string_pretty(&config).expect("failed to serialize config")
+        );
+        return;
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
-        .expect("tokio runtime build failed")
-        .block_on(async_main());
+        .expect("tokio runtime build failed");
+    let telemetry = {
+        let _guard = runtime.enter();
+        telemetry::init(&config)
+    };
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => runtime.block_on(async_main(config)),
+        command => {
+            if let Err(err) = runtime.block_on(cli::run(command, &config)) {
+                eprintln!("error: {err:#}");
+                drop(telemetry);
+                std::process::exit(1);
+            }
+        }
+    }
 }
 
-async fn async_main() {
-    let (server_handle, join_handle) = web_server().await.unwrap();
+async fn async_main(config: ServerConfig) {
+    let (server_handle, join_handle) = web_server(&config).await.unwrap();
 
     listen_shutdown_signal(server_handle).await;
-    if let Err(_) = tokio::time::timeout(Duration::from_millis(3500), join_handle).await {
+    if tokio::time::timeout(Duration::from_millis(3500), join_handle)
+        .await
+        .is_err()
+    {
         tracing::warn!("shutdown server timeout, force termination");
     } else {
         tokio::time::sleep(Duration::from_millis(100)).await;
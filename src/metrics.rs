@@ -0,0 +1,214 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// 耗时直方图的上界, 单位秒
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        for (bucket, le) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{le}\"}} {bucket}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+/// 进程内的运行指标, 以Prometheus的文本格式输出
+#[derive(Default)]
+pub struct Metrics {
+    /// 请求方法, 路由和状态码
+    requests: Mutex<BTreeMap<(String, String, u16), Histogram>>,
+    /// `Repository`的方法名和是否出错
+    queries: Mutex<BTreeMap<(&'static str, bool), Histogram>>,
+    logins: AtomicU64,
+    failed_logins: AtomicU64,
+    /// 本进程签发的会话的过期时间
+    ///
+    /// 会话保存在cookie中, 服务器没有会话列表, 只能统计本进程启动后登录的会话
+    sessions: Mutex<Vec<Instant>>,
+    session_ttl: Duration,
+}
+
+impl Metrics {
+    pub fn new(session_ttl: Duration) -> Self {
+        Metrics {
+            session_ttl,
+            ..Default::default()
+        }
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        self.requests
+            .lock()
+            .unwrap()
+            .entry((method.to_owned(), route.to_owned(), status))
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn observe_query(&self, operation: &'static str, ok: bool, elapsed: Duration) {
+        self.queries
+            .lock()
+            .unwrap()
+            .entry((operation, ok))
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn observe_login(&self, success: bool) {
+        let counter = if success {
+            &self.logins
+        } else {
+            &self.failed_logins
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if success {
+            let mut sessions = self.sessions.lock().unwrap();
+            let now = Instant::now();
+            sessions.retain(|expires| *expires > now);
+            sessions.push(now + self.session_ttl);
+        }
+    }
+
+    /// 修改密码后其他会话全部失效, 只有当前会话继续保持登录
+    pub fn revoke_sessions(&self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.clear();
+        sessions.push(Instant::now() + self.session_ttl);
+    }
+
+    fn active_sessions(&self) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|expires| *expires > now);
+        sessions.len()
+    }
+
+    pub fn render(&self, out: &mut String) {
+        out.push_str("# HELP bulog_http_request_duration_seconds HTTP request latency by route\n");
+        out.push_str("# TYPE bulog_http_request_duration_seconds histogram\n");
+        for ((method, route, status), histogram) in self.requests.lock().unwrap().iter() {
+            let labels = format!(
+                "method=\"{method}\",route=\"{}\",status=\"{status}\"",
+                escape(route)
+            );
+            histogram.write(out, "bulog_http_request_duration_seconds", &labels);
+        }
+
+        out.push_str("# HELP bulog_db_query_duration_seconds Database operation latency\n");
+        out.push_str("# TYPE bulog_db_query_duration_seconds histogram\n");
+        for ((operation, ok), histogram) in self.queries.lock().unwrap().iter() {
+            let result = if *ok { "ok" } else { "error" };
+            let labels = format!("operation=\"{operation}\",result=\"{result}\"");
+            histogram.write(out, "bulog_db_query_duration_seconds", &labels);
+        }
+
+        out.push_str("# HELP bulog_logins_total Login attempts\n");
+        out.push_str("# TYPE bulog_logins_total counter\n");
+        let _ = writeln!(
+            out,
+            "bulog_logins_total{{result=\"success\"}} {}",
+            self.logins.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "bulog_logins_total{{result=\"failure\"}} {}",
+            self.failed_logins.load(Ordering::Relaxed)
+        );
+        write_gauge(
+            out,
+            "bulog_active_sessions",
+            "Unexpired sessions issued since the process started, reset when the password changes",
+            self.active_sessions(),
+        );
+    }
+}
+
+/// 写入一个没有标签的指标
+pub fn write_gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = write!(
+        out,
+        "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"
+    );
+}
+
+/// 标签值中的反斜杠, 引号和换行需要转义
+pub fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Metrics, escape};
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new(Duration::from_secs(60));
+        metrics.observe_login(true);
+        metrics.observe_login(true);
+        metrics.observe_request("GET", "/v1/config", 200, Duration::from_millis(20));
+        metrics.observe_request("GET", "/v1/config", 200, Duration::from_secs(20));
+        metrics.observe_query("query_config", true, Duration::from_millis(1));
+        metrics.observe_login(false);
+
+        let mut out = String::new();
+        metrics.render(&mut out);
+        let labels = "method=\"GET\",route=\"/v1/config\",status=\"200\"";
+        assert!(out.contains(&format!(
+            "bulog_http_request_duration_seconds_bucket{{{labels},le=\"0.01\"}} 0\n"
+        )));
+        assert!(out.contains(&format!(
+            "bulog_http_request_duration_seconds_bucket{{{labels},le=\"0.025\"}} 1\n"
+        )));
+        assert!(out.contains(&format!(
+            "bulog_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 2\n"
+        )));
+        assert!(out.contains(&format!(
+            "bulog_http_request_duration_seconds_count{{{labels}}} 2\n"
+        )));
+        assert!(out.contains(
+            "bulog_db_query_duration_seconds_count{operation=\"query_config\",result=\"ok\"} 1\n"
+        ));
+        assert!(out.contains("bulog_logins_total{result=\"failure\"} 1\n"));
+        assert!(out.contains("bulog_active_sessions 2\n"));
+        metrics.revoke_sessions();
+        out.clear();
+        metrics.render(&mut out);
+        assert!(out.contains("bulog_active_sessions 1\n"));
+        assert_eq!(escape("a\"b\\"), "a\\\"b\\\\");
+    }
+}
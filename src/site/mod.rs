@@ -0,0 +1,371 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use render::{Listing, Site, media_path, page_path, post_path, tag_path};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use smol_str::SmolStr;
+
+use crate::{
+    db::{model::post::PostRecord, repo::Repository},
+    storage::DynStorage,
+};
+
+pub mod render;
+
+/// 页面模板发生变化时递增, 之前导出的页面会全部重新生成
+const RENDER_VERSION: u32 = 1;
+
+/// 记录上次导出的内容, 用于增量导出
+const MANIFEST_FILE: &str = ".bulog-site.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SiteManifest {
+    /// 站点设置和模板版本的哈希, 变化时所有页面都需要重新生成
+    site: String,
+    /// 文章id和内容的哈希
+    posts: BTreeMap<String, String>,
+    /// 导出的所有文件, 下次导出时删除不再需要的文件
+    files: BTreeSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct SiteReport {
+    /// 重新生成的文章页面
+    pub rendered: usize,
+    /// 没有变化而跳过的文章页面
+    pub unchanged: usize,
+    /// 内容有变化而写入的文件, 包括列表页面和订阅
+    pub written: usize,
+    /// 删除的已经不存在的文章和列表页面
+    pub removed: usize,
+}
+
+/// 把已发布的文章导出为静态站点, `full`为false时只重新生成有变化的文章页面
+pub async fn export_site(
+    repo: &dyn Repository,
+    storage: &DynStorage,
+    dir: &Path,
+    base_url: &str,
+    full: bool,
+) -> anyhow::Result<SiteReport> {
+    let config = repo.query_config().await?;
+    let posts = published_posts(repo).await?;
+
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let mut report = SiteReport::default();
+    let mut files = BTreeSet::new();
+
+    let mut site = Site {
+        config: &config,
+        base_url,
+        media: Default::default(),
+        cache: repo.cache(),
+    };
+    let mut page = 0;
+    loop {
+        let records = repo.query_media_by_page(page, 100).await?;
+        for media in &records {
+            let path = media_path(&media.id, &file_name(&media.filename));
+            // 内容相同的媒体id不会变化, 已经导出的文件不需要重新写入
+            if !file_path(dir, &path).exists() {
+                let data = storage
+                    .get(&media.hash)
+                    .await?
+                    .with_context(|| format!("file of media {} is missing", media.id))?;
+                write_file(dir, &path, &data)?;
+                report.written += 1;
+            }
+            files.insert(path);
+            site.media
+                .insert(media.id.clone(), file_name(&media.filename));
+        }
+        if records.len() < 100 {
+            break;
+        }
+        page += 1;
+    }
+
+    let site_hash = hash(&(RENDER_VERSION, base_url, &config));
+    let previous = match std::fs::read_to_string(dir.join(MANIFEST_FILE)) {
+        Ok(manifest) => sonic_rs::from_str::<SiteManifest>(&manifest).unwrap_or_default(),
+        Err(_) => SiteManifest::default(),
+    };
+    let mut manifest = SiteManifest {
+        site: site_hash.clone(),
+        ..Default::default()
+    };
+    let reuse = !full && previous.site == site_hash;
+
+    for post in &posts {
+        let path = format!("{}index.html", post_path(post));
+        let post_hash = hash(post);
+        if reuse
+            && previous.posts.get(&*post.id) == Some(&post_hash)
+            && file_path(dir, &path).exists()
+        {
+            report.unchanged += 1;
+        } else {
+            write_file(dir, &path, site.render_post(post).as_bytes())?;
+            report.rendered += 1;
+        }
+        manifest.posts.insert(post.id.to_string(), post_hash);
+        files.insert(path);
+    }
+
+    // 列表页面依赖所有文章, 每次都重新生成, 内容没有变化时不写入
+    let mut pages = Vec::new();
+    let per_page = (config.posts_per_page as usize).max(1);
+    let mut listed = posts.iter().collect::<Vec<_>>();
+    // 置顶的文章只在首页列表中排在前面
+    listed.sort_by_key(|post| !post.pinned);
+    paginate(&mut pages, &site, &config.title, "/", &listed, per_page);
+
+    let mut tags: BTreeMap<String, (&SmolStr, Vec<&PostRecord>)> = BTreeMap::new();
+    for post in &posts {
+        for tag in &post.tags {
+            // 不安全的标签没有列表页面
+            let Some(path) = tag_path(tag) else {
+                continue;
+            };
+            tags.entry(path)
+                .or_insert_with(|| (tag, Vec::new()))
+                .1
+                .push(post);
+        }
+    }
+    for (path, (tag, tagged)) in &tags {
+        let title = format!("#{tag}");
+        paginate(&mut pages, &site, &title, path, tagged, per_page);
+    }
+
+    let posts = posts.iter().collect::<Vec<_>>();
+    if config.feed.enabled {
+        pages.push(("/feed.xml".to_owned(), site.render_feed(&posts)));
+    }
+    let tags = tags.values().map(|(tag, _)| *tag).collect::<Vec<_>>();
+    pages.push((
+        "/sitemap.xml".to_owned(),
+        site.render_sitemap(&posts, &tags),
+    ));
+
+    for (path, content) in pages {
+        if std::fs::read(file_path(dir, &path)).ok().as_deref() != Some(content.as_bytes()) {
+            write_file(dir, &path, content.as_bytes())?;
+            report.written += 1;
+        }
+        files.insert(path);
+    }
+    report.written += report.rendered;
+
+    for stale in previous.files.difference(&files) {
+        let path = file_path(dir, stale);
+        if path.is_file() {
+            std::fs::remove_file(&path)?;
+            report.removed += 1;
+        }
+    }
+    manifest.files = files;
+    std::fs::write(dir.join(MANIFEST_FILE), sonic_rs::to_string(&manifest)?)?;
+    Ok(report)
+}
+
+/// 按发布时间倒序排列的所有非草稿文章
+async fn published_posts(repo: &dyn Repository) -> anyhow::Result<Vec<PostRecord>> {
+    let mut posts = Vec::new();
+    let mut page = 0;
+    loop {
+        let records = repo.query_posts_by_page(page, 100, false).await?;
+        let len = records.len();
+        posts.extend(records.into_iter().filter(|post| !post.draft));
+        if len < 100 {
+            break Ok(posts);
+        }
+        page += 1;
+    }
+}
+
+fn paginate(
+    pages: &mut Vec<(String, String)>,
+    site: &Site,
+    title: &str,
+    path: &str,
+    posts: &[&PostRecord],
+    per_page: usize,
+) {
+    let total_pages = posts.len().div_ceil(per_page).max(1);
+    for page in 1..=total_pages {
+        let start = (page - 1) * per_page;
+        let end = (start + per_page).min(posts.len());
+        let html = site.render_listing(&Listing {
+            title,
+            posts: &posts[start..end],
+            page,
+            total_pages,
+            path,
+        });
+        pages.push((format!("{}index.html", page_path(path, page)), html));
+    }
+}
+
+/// 上传时的文件名可能包含路径分隔符
+fn file_name(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    if name.is_empty() || name.starts_with('.') {
+        format!("file{name}")
+    } else {
+        name.to_owned()
+    }
+}
+
+fn hash(value: &impl Serialize) -> String {
+    let json = sonic_rs::to_string(value).unwrap_or_default();
+    format!("{:x}", Sha256::digest(json))
+}
+
+/// `path`是以`/`开头的站点路径, 其中的百分号编码会被还原为文件名
+fn file_path(dir: &Path, path: &str) -> PathBuf {
+    dir.join(decode_path(path.trim_start_matches('/')))
+}
+
+fn write_file(dir: &Path, path: &str, data: &[u8]) -> anyhow::Result<()> {
+    let path = file_path(dir, path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, data).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%'
+            && let Some(byte) = path
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use mime_guess::mime;
+
+    use crate::{
+        db::{
+            model::{
+                media::store_media,
+                post::{
+                    NewPost, PostRecordOption, create_post, delete_post, insert_post, update_post,
+                },
+            },
+            test_db,
+        },
+        storage::{DynStorage, MemoryStorage, TempDir},
+    };
+
+    use super::{export_site, render::summary};
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            summary("# Title\n\nSome *text*\n<!--more-->\nrest"),
+            "Title Some text"
+        );
+        assert_eq!(summary(&"a".repeat(300)).chars().count(), 201);
+    }
+
+    #[tokio::test]
+    async fn test_export_site() -> anyhow::Result<()> {
+        let db = test_db().await?;
+        let storage: DynStorage = Arc::new(MemoryStorage::default());
+        let (media, _) = store_media(
+            &db,
+            &storage,
+            "a b.png".into(),
+            mime::IMAGE_PNG,
+            b"png".to_vec(),
+        )
+        .await?;
+        let first = insert_post(
+            &db,
+            NewPost {
+                title: "Hello <world>".into(),
+                content: format!("![img](/v1/media/{}/file)", media.id).into(),
+                tags: vec!["Rust Lang".into(), "..".into(), ".".into()],
+                slug: Some("hello".into()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        let second = create_post(&db, "second".into(), "text".into(), false, false).await?;
+        create_post(&db, "draft".into(), "secret".into(), true, false).await?;
+
+        let dir = TempDir::new("bulog-site");
+        let base_url = "https://blog.example.com/";
+        let report = export_site(&db, &storage, &dir, base_url, false).await?;
+        assert_eq!((report.rendered, report.unchanged), (2, 0));
+
+        let post = std::fs::read_to_string(dir.join("posts/hello/index.html"))?;
+        assert!(post.contains("<h1>Hello &lt;world&gt;</h1>"));
+        assert!(post.contains(&format!(
+            "https://blog.example.com/media/{}/a%20b.png",
+            media.id
+        )));
+        assert!(post.contains("https://blog.example.com/tags/rust-lang/"));
+        assert!(dir.join(format!("media/{}/a b.png", media.id)).is_file());
+        assert!(dir.join("tags/rust-lang/index.html").is_file());
+        // 不安全的标签不会生成页面, 也不会覆盖首页
+        assert!(!dir.join("tags/index.html").exists());
+        assert!(!post.contains("/tags/./") && !post.contains("/tags/../"));
+        let index = std::fs::read_to_string(dir.join("index.html"))?;
+        assert!(index.contains("second") && !index.contains("draft"));
+        let feed = std::fs::read_to_string(dir.join("feed.xml"))?;
+        assert!(feed.contains("<link>https://blog.example.com/posts/hello/</link>"));
+        let sitemap = std::fs::read_to_string(dir.join("sitemap.xml"))?;
+        assert!(sitemap.contains(&format!("https://blog.example.com/posts/{second}/")));
+
+        // 没有变化时不重新生成任何页面
+        let report = export_site(&db, &storage, &dir, base_url, false).await?;
+        assert_eq!(
+            (report.rendered, report.unchanged, report.written),
+            (0, 2, 0)
+        );
+
+        update_post(
+            &db,
+            first,
+            PostRecordOption {
+                title: Some("updated".into()),
+                ..Default::default()
+            },
+        )
+        .await?;
+        delete_post(&db, second.clone()).await?;
+        let report = export_site(&db, &storage, &dir, base_url, false).await?;
+        assert_eq!(
+            (report.rendered, report.unchanged, report.removed),
+            (1, 0, 1)
+        );
+        assert!(!dir.join(format!("posts/{second}/index.html")).exists());
+        assert!(std::fs::read_to_string(dir.join("posts/hello/index.html"))?.contains("updated"));
+
+        let report = export_site(&db, &storage, &dir, base_url, true).await?;
+        assert_eq!(report.rendered, 1);
+        Ok(())
+    }
+}
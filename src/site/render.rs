@@ -0,0 +1,334 @@
+use std::{collections::HashMap, fmt::Write};
+
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{Event, Options, Parser, TagEnd};
+use smol_str::SmolStr;
+
+use crate::db::{
+    model::{config::ConfigRecord, media::file_url, post::PostRecord},
+    repo::Cache,
+};
+
+/// 摘要的最大字符数
+const SUMMARY_LENGTH: usize = 200;
+
+/// 正文中这个标记之前的部分作为摘要
+const MORE_MARKER: &str = "<!--more-->";
+
+/// 渲染页面需要的站点信息, 所有链接都是以`base_url`开头的绝对地址
+pub struct Site<'a> {
+    pub config: &'a ConfigRecord,
+    pub base_url: &'a str,
+    /// 媒体id和导出后的文件名, 正文中指向媒体接口的链接会被替换为静态文件
+    pub media: HashMap<SmolStr, String>,
+    /// 渲染缓存, 关闭缓存时为空
+    pub cache: Option<&'a Cache>,
+}
+
+/// 分页列表中的一页
+pub struct Listing<'a> {
+    pub title: &'a str,
+    pub posts: &'a [&'a PostRecord],
+    /// 从1开始
+    pub page: usize,
+    pub total_pages: usize,
+    /// 列表第一页的路径, 例如`/`或`/tags/rust/`
+    pub path: &'a str,
+}
+
+impl Site<'_> {
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url.trim_end_matches('/'))
+    }
+
+    pub fn post_html(&self, post: &PostRecord) -> String {
+        let mut content = post.content.to_string();
+        for (id, filename) in &self.media {
+            content = content.replace(&file_url(id), &self.url(&media_path(id, filename)));
+        }
+        match self.cache {
+            Some(cache) => cache.render(&content, markdown_html).to_string(),
+            None => markdown_html(&content),
+        }
+    }
+
+    pub fn render_post(&self, post: &PostRecord) -> String {
+        let mut body = format!(
+            "<article>\n<h1>{}</h1>\n{}\n{}</article>\n",
+            escape(&post.title),
+            self.post_meta(post),
+            self.post_html(post)
+        );
+        if !post.tags.is_empty() {
+            body.push_str("<nav class=\"tags\">");
+            for tag in &post.tags {
+                // 不能作为目录名的标签没有列表页面, 只显示文字
+                match tag_path(tag) {
+                    Some(path) => {
+                        let _ = write!(
+                            body,
+                            "<a href=\"{}\">#{}</a> ",
+                            escape(&self.url(&path)),
+                            escape(tag)
+                        );
+                    }
+                    None => {
+                        let _ = write!(body, "<span>#{}</span> ", escape(tag));
+                    }
+                }
+            }
+            body.push_str("</nav>\n");
+        }
+        self.layout(&post.title, &body)
+    }
+
+    pub fn render_listing(&self, listing: &Listing) -> String {
+        let mut body = String::new();
+        for post in listing.posts {
+            let _ = write!(
+                body,
+                "<article>\n<h2><a href=\"{}\">{}</a></h2>\n{}\n<p>{}</p>\n</article>\n",
+                escape(&self.url(&post_path(post))),
+                escape(&post.title),
+                self.post_meta(post),
+                escape(&summary(&post.content))
+            );
+        }
+        body.push_str("<nav class=\"pagination\">");
+        if listing.page > 1 {
+            let _ = write!(
+                body,
+                "<a rel=\"prev\" href=\"{}\">&larr;</a> ",
+                escape(&self.url(&page_path(listing.path, listing.page - 1)))
+            );
+        }
+        let _ = write!(body, "{} / {}", listing.page, listing.total_pages);
+        if listing.page < listing.total_pages {
+            let _ = write!(
+                body,
+                " <a rel=\"next\" href=\"{}\">&rarr;</a>",
+                escape(&self.url(&page_path(listing.path, listing.page + 1)))
+            );
+        }
+        body.push_str("</nav>\n");
+        self.layout(listing.title, &body)
+    }
+
+    /// RSS 2.0, 文章数量和是否输出全文由站点设置决定
+    pub fn render_feed(&self, posts: &[&PostRecord]) -> String {
+        let config = self.config;
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = write!(
+            xml,
+            "<rss version=\"2.0\"><channel>\n<title>{}</title>\n<link>{}</link>\n<description>{}</description>\n<language>{}</language>\n",
+            escape(&config.title),
+            escape(&self.url("/")),
+            escape(&config.description),
+            escape(&config.language)
+        );
+        for post in posts.iter().take(config.feed.items as usize) {
+            let link = self.url(&post_path(post));
+            let description = if config.feed.full_content {
+                self.post_html(post)
+            } else {
+                summary(&post.content)
+            };
+            let _ = write!(
+                xml,
+                "<item>\n<title>{}</title>\n<link>{}</link>\n<guid>{}</guid>\n<pubDate>{}</pubDate>\n<description>{}</description>\n",
+                escape(&post.title),
+                escape(&link),
+                escape(&link),
+                datetime(post).to_rfc2822(),
+                escape(&description)
+            );
+            for tag in &post.tags {
+                let _ = writeln!(xml, "<category>{}</category>", escape(tag));
+            }
+            xml.push_str("</item>\n");
+        }
+        xml.push_str("</channel></rss>\n");
+        xml
+    }
+
+    pub fn render_sitemap(&self, posts: &[&PostRecord], tags: &[&SmolStr]) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        );
+        let _ = writeln!(xml, "<url><loc>{}</loc></url>", escape(&self.url("/")));
+        for post in posts {
+            let _ = writeln!(
+                xml,
+                "<url><loc>{}</loc><lastmod>{}</lastmod></url>",
+                escape(&self.url(&post_path(post))),
+                datetime(post).format("%Y-%m-%d")
+            );
+        }
+        for path in tags.iter().filter_map(|tag| tag_path(tag)) {
+            let _ = writeln!(xml, "<url><loc>{}</loc></url>", escape(&self.url(&path)));
+        }
+        xml.push_str("</urlset>\n");
+        xml
+    }
+
+    fn post_meta(&self, post: &PostRecord) -> String {
+        let time = datetime(post);
+        format!(
+            "<time datetime=\"{}\">{}</time>",
+            time.to_rfc3339(),
+            time.format("%Y-%m-%d")
+        )
+    }
+
+    fn layout(&self, title: &str, body: &str) -> String {
+        let config = self.config;
+        let page_title = if title == config.title {
+            escape(title)
+        } else {
+            format!("{} - {}", escape(title), escape(&config.title))
+        };
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{page_title}</title>\n<meta name=\"description\" content=\"{}\">\n",
+            escape(&config.language),
+            escape(&config.description)
+        );
+        if config.feed.enabled {
+            let _ = writeln!(
+                html,
+                "<link rel=\"alternate\" type=\"application/rss+xml\" href=\"{}\">",
+                escape(&self.url("/feed.xml"))
+            );
+        }
+        let _ = write!(
+            html,
+            "</head>\n<body>\n<header><a href=\"{}\">{}</a><p>{}</p></header>\n<main>\n{body}</main>\n<footer>",
+            escape(&self.url("/")),
+            escape(&config.title),
+            escape(&config.description)
+        );
+        for link in &config.social_links {
+            let _ = write!(
+                html,
+                "<a href=\"{}\">{}</a> ",
+                escape(&link.url),
+                escape(&link.name)
+            );
+        }
+        // 页脚是管理员填写的HTML, 不需要转义
+        let _ = write!(html, "{}</footer>\n</body>\n</html>\n", config.footer_html);
+        html
+    }
+}
+
+pub fn markdown_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, Options::all()));
+    html
+}
+
+/// 有slug时使用slug作为地址, 保持和导入前的博客一致
+pub fn post_path(post: &PostRecord) -> String {
+    let key = post
+        .slug
+        .as_deref()
+        .filter(|slug| is_safe_segment(slug))
+        .unwrap_or(&post.id);
+    format!("/posts/{}/", encode_segment(key))
+}
+
+pub fn tag_path(tag: &str) -> Option<String> {
+    tag_segment(tag).map(|segment| format!("/tags/{}/", encode_segment(&segment)))
+}
+
+/// 标签中的空白和`/`替换为`-`, 用作目录名, 替换后仍然不安全时返回`None`
+///
+/// 导入的标签不可信, `..`之类的标签会写到列表目录之外
+fn tag_segment(tag: &str) -> Option<String> {
+    let segment = tag
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_whitespace() || c == '/' {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .to_lowercase();
+    is_safe_segment(&segment).then_some(segment)
+}
+
+/// 第一页就是列表本身的路径
+pub fn page_path(path: &str, page: usize) -> String {
+    if page <= 1 {
+        path.to_owned()
+    } else {
+        format!("{path}page/{page}/")
+    }
+}
+
+pub fn media_path(id: &str, filename: &str) -> String {
+    format!("/media/{id}/{}", encode_segment(filename))
+}
+
+/// 可以直接作为文件名使用的slug
+pub fn is_safe_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && !segment.starts_with('.')
+        && !segment.contains(['/', '\\', '?', '#', '%'])
+}
+
+fn encode_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+fn datetime(post: &PostRecord) -> DateTime<Utc> {
+    // surrealdb没有提供转换为chrono时间的公开接口
+    post.created_time.into_inner_ref().0
+}
+
+/// 优先使用`<!--more-->`之前的部分, 否则截取正文的纯文本
+pub fn summary(markdown: &str) -> String {
+    let markdown = markdown
+        .split_once(MORE_MARKER)
+        .map_or(markdown, |(summary, _)| summary);
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(part) | Event::Code(part) => text.push_str(&part),
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(TagEnd::Paragraph | TagEnd::Heading(_)) => text.push(' '),
+            _ => {}
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(SUMMARY_LENGTH) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text,
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,65 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+
+use super::Storage;
+use crate::nano_id::nanoid;
+
+/// 保存在本地目录中, 按`key`的前两个字符分目录, 避免单个目录下文件过多
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)?;
+        Ok(LocalStorage { root })
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        if key.len() < 3 || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '.') {
+            anyhow::bail!("invalid storage key: {key}");
+        }
+        Ok(self.root.join(&key[..2]).join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // 先写入临时文件再重命名, 避免读到写了一半的文件
+        let tmp = path.with_file_name(format!("{key}.{}.tmp", nanoid(8)));
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(tmp, path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        tokio::fs::try_exists(self.path(key)?)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+use hashbrown::HashMap;
+use tokio::sync::RwLock;
+
+use super::Storage;
+
+/// test only
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        self.files.write().await.insert(key.to_owned(), data);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.files.read().await.get(key).cloned())
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        Ok(self.files.read().await.contains_key(key))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.files.write().await.remove(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::config::UploadConfig;
+
+pub use local::LocalStorage;
+#[cfg(test)]
+pub use memory::MemoryStorage;
+
+mod local;
+#[cfg(test)]
+mod memory;
+
+/// 上传文件的存储后端, 以`key`寻址, 媒体文件使用内容的哈希作为`key`
+#[async_trait]
+pub trait Storage: Send + Sync + 'static {
+    async fn put(&self, key: &str, data: Vec<u8>) -> anyhow::Result<()>;
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool>;
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+pub type DynStorage = Arc<dyn Storage>;
+
+/// test only, 带随机后缀的临时目录路径, 离开作用域时删除, 断言失败时也会清理
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(prefix: &str) -> Self {
+        let name = format!("{prefix}-{}", crate::nano_id::nanoid(8));
+        TempDir(std::env::temp_dir().join(name))
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<std::path::Path> for TempDir {
+    fn as_ref(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+pub fn storage(config: &UploadConfig) -> anyhow::Result<DynStorage> {
+    Ok(Arc::new(LocalStorage::new(&config.dir)?))
+}
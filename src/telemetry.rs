@@ -0,0 +1,276 @@
+use tracing_subscriber::{
+    EnvFilter, fmt, fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt,
+};
+
+use crate::config::{LogFormat, ServerConfig};
+
+/// 进程退出前需要保留, 释放时把缓冲中的span发送给collector
+#[derive(Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take()
+            && let Err(err) = provider.shutdown()
+        {
+            eprintln!("failed to flush traces: {err}");
+        }
+    }
+}
+
+/// 初始化日志输出, 配置了collector时同时导出trace
+///
+/// OTLP导出器需要在tokio运行时中创建
+pub fn init(config: &ServerConfig) -> Telemetry {
+    let log = &config.log;
+    let span_events = if log.spans {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+    let (text, json) = match log.format {
+        LogFormat::Text => (Some(fmt::layer().with_span_events(span_events)), None),
+        LogFormat::Json => (
+            None,
+            Some(fmt::layer().json().with_span_events(span_events)),
+        ),
+    };
+    let registry = tracing_subscriber::registry()
+        .with(EnvFilter::new(&log.filter))
+        .with(text)
+        .with(json);
+
+    #[cfg(feature = "otel")]
+    match otel::layer(&config.telemetry) {
+        Ok(Some((layer, provider))) => {
+            registry.with(layer).init();
+            tracing::info!(
+                "exporting traces to {}",
+                config.telemetry.endpoint.as_deref().unwrap_or_default()
+            );
+            return Telemetry {
+                provider: Some(provider),
+            };
+        }
+        Ok(None) => registry.init(),
+        Err(err) => {
+            registry.init();
+            tracing::error!("failed to set up trace export: {err:#}");
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    registry.init();
+    Telemetry::default()
+}
+
+#[cfg(feature = "otel")]
+pub mod otel {
+    use opentelemetry::{
+        Context, KeyValue, global,
+        propagation::{Extractor, TextMapPropagator},
+        trace::TracerProvider as _,
+    };
+    use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{
+        Resource, propagation::TraceContextPropagator, runtime, trace::Sampler,
+        trace::TracerProvider,
+    };
+    use salvo::http::HeaderMap;
+    use tracing::Subscriber;
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::registry::LookupSpan;
+
+    use crate::config::{OtlpProtocol, TelemetryConfig};
+
+    /// 没有配置collector时返回`None`
+    pub fn layer<S>(
+        config: &TelemetryConfig,
+    ) -> anyhow::Result<
+        Option<(
+            OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>,
+            TracerProvider,
+        )>,
+    >
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let Some(endpoint) = &config.endpoint else {
+            return Ok(None);
+        };
+        let exporter = match config.protocol {
+            OtlpProtocol::Grpc => SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?,
+            OtlpProtocol::Http => SpanExporter::builder()
+                .with_http()
+                .with_protocol(Protocol::HttpBinary)
+                .with_endpoint(http_endpoint(endpoint))
+                .build()?,
+        };
+
+        let mut attributes = vec![
+            KeyValue::new("service.name", config.service_name.clone()),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ];
+        attributes.extend(
+            config
+                .resource
+                .iter()
+                .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+        );
+        let provider = TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                config.sample_ratio,
+            ))))
+            .with_resource(Resource::default().merge(&Resource::new(attributes)))
+            .build();
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer = provider.tracer("bulog");
+        Ok(Some((
+            tracing_opentelemetry::layer().with_tracer(tracer),
+            provider,
+        )))
+    }
+
+    /// http/protobuf的地址需要包含路径, 只填写了collector的地址时使用标准的路径
+    fn http_endpoint(endpoint: &str) -> String {
+        let path = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, rest)| rest)
+            .split_once('/')
+            .map_or("", |(_, path)| path);
+        if path.is_empty() {
+            format!("{}/v1/traces", endpoint.trim_end_matches('/'))
+        } else {
+            endpoint.to_owned()
+        }
+    }
+
+    /// 从请求头中读取W3C trace context, 作为请求span的上级
+    pub fn extract(headers: &HeaderMap) -> Context {
+        struct Headers<'a>(&'a HeaderMap);
+
+        impl Extractor for Headers<'_> {
+            fn get(&self, key: &str) -> Option<&str> {
+                self.0.get(key).and_then(|value| value.to_str().ok())
+            }
+
+            fn keys(&self) -> Vec<&str> {
+                self.0.keys().map(|key| key.as_str()).collect()
+            }
+        }
+
+        TraceContextPropagator::new().extract(&Headers(headers))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::sync::{Arc, Mutex};
+
+        use opentelemetry::trace::TraceContextExt;
+        use salvo::{
+            Depot, FlowCtrl, Handler, Listener, Request, Response, Router, Server, async_trait,
+            conn::{Acceptor, TcpListener},
+            http::HeaderMap,
+        };
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        use tracing_subscriber::{layer::SubscriberExt, registry::Registry};
+
+        use super::{extract, http_endpoint, layer};
+        use crate::config::{OtlpProtocol, TelemetryConfig};
+
+        /// 收到的请求路径和内容
+        type Received = (String, Vec<u8>);
+
+        /// 代替collector, 记录收到的请求
+        #[derive(Clone, Default)]
+        struct Collector(Arc<Mutex<Vec<Received>>>);
+
+        #[async_trait]
+        impl Handler for Collector {
+            async fn handle(
+                &self,
+                req: &mut Request,
+                _depot: &mut Depot,
+                _res: &mut Response,
+                _ctrl: &mut FlowCtrl,
+            ) {
+                let path = req.uri().path().to_owned();
+                let body = req.payload().await.unwrap().to_vec();
+                self.0.lock().unwrap().push((path, body));
+            }
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn test_export() -> anyhow::Result<()> {
+            assert_eq!(
+                http_endpoint("http://localhost:4318/"),
+                "http://localhost:4318/v1/traces"
+            );
+            assert_eq!(
+                http_endpoint("http://localhost:4318/custom"),
+                "http://localhost:4318/custom"
+            );
+
+            let collector = Collector::default();
+            let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+            let addr = acceptor.holdings()[0]
+                .local_addr
+                .clone()
+                .into_std()
+                .unwrap();
+            let server = Server::new(acceptor);
+            let handle = server.handle();
+            tokio::spawn(server.serve(Router::with_path("<**>").post(collector.clone())));
+
+            let config = TelemetryConfig {
+                endpoint: Some(format!("http://{addr}")),
+                protocol: OtlpProtocol::Http,
+                resource: [("deployment.environment".to_owned(), "test".to_owned())].into(),
+                ..Default::default()
+            };
+            let (layer, provider) = layer::<Registry>(&config)?.unwrap();
+
+            let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "traceparent",
+                format!("00-{trace_id}-00f067aa0ba902b7-01").parse()?,
+            );
+            let parent = extract(&headers);
+            assert_eq!(
+                parent.span().span_context().trace_id().to_string(),
+                trace_id
+            );
+
+            let subscriber = tracing_subscriber::registry().with(layer);
+            tracing::subscriber::with_default(subscriber, || {
+                let span = tracing::info_span!("request");
+                span.set_parent(parent);
+                span.in_scope(|| tracing::info_span!("query_config").in_scope(|| {}));
+            });
+            tokio::task::spawn_blocking(move || provider.shutdown()).await??;
+            handle.stop_graceful(None);
+
+            let requests = collector.0.lock().unwrap();
+            let (path, body) = &requests[0];
+            assert_eq!(path, "/v1/traces");
+            let contains =
+                |needle: &[u8]| body.windows(needle.len()).any(|window| window == needle);
+            let trace_id = (0..16)
+                .map(|i| u8::from_str_radix(&trace_id[i * 2..i * 2 + 2], 16).unwrap())
+                .collect::<Vec<_>>();
+            assert!(contains(&trace_id));
+            assert!(contains(b"bulog"));
+            assert!(contains(b"query_config"));
+            assert!(contains(b"deployment.environment"));
+            Ok(())
+        }
+    }
+}
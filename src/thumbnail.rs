@@ -0,0 +1,39 @@
+use image::imageops::FilterType;
+
+/// 响应式图片的候选宽度, 只生成比原图窄的尺寸
+pub const VARIANT_WIDTHS: [u32; 4] = [320, 640, 1024, 1600];
+
+/// 缩略图的webp质量, 0到100, 无损编码的结果经常比原来的jpeg还大
+pub const WEBP_QUALITY: f32 = 80.0;
+
+/// 可以被解码并生成缩略图的图片类型
+pub fn is_resizable(mime: &str) -> bool {
+    matches!(
+        mime,
+        "image/png" | "image/jpeg" | "image/webp" | "image/gif"
+    )
+}
+
+pub fn variant_widths(width: u32) -> impl Iterator<Item = u32> {
+    VARIANT_WIDTHS.into_iter().filter(move |w| *w < width)
+}
+
+/// 缩略图在存储后端中的key, 与原图共用哈希前缀
+pub fn variant_key(hash: &str, width: u32) -> String {
+    format!("{hash}.w{width}.webp")
+}
+
+/// 按宽度等比缩放并用有损压缩转换为webp
+pub async fn resize_to_webp(data: Vec<u8>, width: u32) -> anyhow::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || {
+        let image = image::load_from_memory(&data)?;
+        let image = image.resize(width, u32::MAX, FilterType::Lanczos3);
+        // 先转换成rgba8保证所有输入格式都能编码
+        let rgba = image.to_rgba8();
+        let webp = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+            .encode_simple(false, WEBP_QUALITY)
+            .map_err(|err| anyhow::anyhow!("failed to encode webp: {err:?}"))?;
+        Ok(webp.to_vec())
+    })
+    .await?
+}
@@ -0,0 +1,76 @@
+use std::time::Instant;
+
+use salvo::{
+    Depot, FlowCtrl, Request, handler,
+    http::{HeaderName, HeaderValue, StatusCode},
+};
+use smol_str::SmolStr;
+use tracing::Instrument;
+
+use crate::nano_id::nanoid;
+
+/// 请求和响应中的请求id, 反向代理已经生成时沿用它的值
+pub static REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// 需要作为`Service`最外层的hoop使用, 整个请求都在`request` span中处理,
+/// 其中的日志和数据库操作的span都会带上请求id
+#[handler]
+pub async fn access_log(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut salvo::Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let request_id = request_id(req);
+    let client_ip = req
+        .remote_addr()
+        .clone()
+        .into_std()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        id = %request_id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        client_ip = %client_ip,
+    );
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        span.set_parent(crate::telemetry::otel::extract(req.headers()));
+    }
+    let start = Instant::now();
+    ctrl.call_next(req, depot, res)
+        .instrument(span.clone())
+        .await;
+
+    let status = res.status_code.unwrap_or(StatusCode::OK);
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    span.in_scope(|| {
+        if status.is_server_error() {
+            tracing::error!(status = status.as_u16(), latency_ms, "request failed");
+        } else {
+            tracing::info!(status = status.as_u16(), latency_ms, "request completed");
+        }
+    });
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(REQUEST_ID.clone(), value);
+    }
+}
+
+/// 只接受长度合理的可见字符, 避免日志注入
+fn request_id(req: &Request) -> SmolStr {
+    req.headers()
+        .get(&REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 128
+                && id
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || b"-_.:".contains(&byte))
+        })
+        .map(SmolStr::from)
+        .unwrap_or_else(|| nanoid(16))
+}
@@ -0,0 +1,87 @@
+use salvo::{
+    Depot, FlowCtrl, Request,
+    compression::{Compression, CompressionLevel},
+    handler,
+    http::{
+        HeaderValue, Method, ResBody, StatusCode,
+        header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH, VARY},
+    },
+};
+use sha2::{Digest, Sha256};
+
+/// 小于这个长度的响应压缩后几乎不会变小
+const MIN_COMPRESS_LENGTH: usize = 256;
+
+/// 需要作为`Service`的hoop使用, 放在`conditional`外层, 这样ETag对应的是压缩前的内容
+///
+/// 已经带有`Content-Encoding`的预压缩文件不会再次压缩
+pub fn compression(enabled: bool) -> Option<Compression> {
+    enabled.then(|| {
+        Compression::new()
+            .disable_all()
+            .enable_zstd(CompressionLevel::Default)
+            .enable_brotli(CompressionLevel::Default)
+            .enable_gzip(CompressionLevel::Default)
+            .min_length(MIN_COMPRESS_LENGTH)
+    })
+}
+
+/// GET请求的响应没有ETag时用内容的哈希生成, 和`If-None-Match`匹配时返回304
+///
+/// 内容可能被压缩, 所以使用弱ETag
+#[handler]
+pub async fn conditional(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut salvo::Response,
+    ctrl: &mut FlowCtrl,
+) {
+    ctrl.call_next(req, depot, res).await;
+    if !matches!(*req.method(), Method::GET | Method::HEAD)
+        || res.status_code.is_some_and(|code| code != StatusCode::OK)
+    {
+        return;
+    }
+    if !res.headers().contains_key(VARY) {
+        res.headers_mut()
+            .insert(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    if res.headers().contains_key(ETAG) {
+        return;
+    }
+    let ResBody::Once(body) = &res.body else {
+        return;
+    };
+    let hash = Sha256::digest(body);
+    let etag = format!(
+        "W/\"{}\"",
+        hash[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+    );
+    if !res.headers().contains_key(CACHE_CONTROL) {
+        // 接口的内容和登录状态有关, 只允许浏览器缓存, 每次使用前重新验证
+        res.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("private, no-cache"));
+    }
+    res.add_header(ETAG, &etag, true).ok();
+    if if_none_match(req, &etag) {
+        res.status_code(StatusCode::NOT_MODIFIED);
+        res.body(ResBody::None);
+    }
+}
+
+/// 请求中的`If-None-Match`是否包含`etag`, 按弱比较的规则忽略`W/`前缀
+pub fn if_none_match(req: &Request, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    req.headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(',').any(|tag| {
+                let tag = tag.trim();
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
+        })
+}
@@ -0,0 +1,40 @@
+use salvo::{
+    cors::{Cors, CorsHandler},
+    http::{Method, header},
+};
+
+use crate::config::CorsConfig;
+
+/// 需要作为`Service`的hoop使用, 预检请求不会匹配任何路由, 也就不会被`initialization_check`拦截
+///
+/// 没有配置允许的源时返回`None`
+pub fn cors_handler(config: &CorsConfig) -> Option<CorsHandler> {
+    let origins = config
+        .origins
+        .iter()
+        .map(|origin| origin.trim_end_matches('/').to_owned())
+        .collect::<Vec<_>>();
+    if origins.is_empty() {
+        return None;
+    }
+    let any = origins.iter().any(|origin| origin == "*");
+    let cors = if any {
+        Cors::new().allow_origin(salvo::cors::Any)
+    } else {
+        Cors::new().allow_origin(&origins)
+    };
+    Some(
+        cors.allow_credentials(config.credentials && !any)
+            .allow_methods(vec![
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+                Method::OPTIONS,
+            ])
+            .allow_headers(vec![header::CONTENT_TYPE, header::IF_NONE_MATCH])
+            .max_age(config.max_age)
+            .into_handler(),
+    )
+}
@@ -1,14 +1,13 @@
-use std::convert::identity;
-
 use salvo::{
     Depot,
     extract::Metadata,
     http::mime::APPLICATION_JSON,
+    oapi::{Components, Content, EndpointArgRegister, Operation, RequestBody, Required, ToSchema},
     session::SessionDepotExt,
 };
 use serde::Deserialize;
 
-use super::resp::Response;
+use super::{SessionEpoch, resp::Response};
 
 pub struct Json<T>(pub T);
 
@@ -29,12 +28,12 @@ where
     {
         if req
             .content_type()
-            .and_then(|mime| (mime == APPLICATION_JSON).then(|| ()))
+            .and_then(|mime| (mime == APPLICATION_JSON).then_some(()))
             .is_none()
         {
             return Err(Response::custom(415, "request content_type is not json"));
         }
-        match sonic_rs::from_slice(&*req.payload().await?) {
+        match sonic_rs::from_slice(req.payload().await?) {
             Ok(json) => Ok(Json(json)),
             Err(err) => Err(Response::custom(
                 400,
@@ -44,10 +43,37 @@ where
     }
 }
 
+/// 解析失败时extractor的错误会以http 400返回
+impl<T> EndpointArgRegister for Json<T>
+where
+    T: ToSchema,
+{
+    fn register(components: &mut Components, operation: &mut Operation, _arg: &str) {
+        operation.request_body = Some(
+            RequestBody::new()
+                .add_content(
+                    APPLICATION_JSON.essence_str(),
+                    Content::new(T::to_schema(components)),
+                )
+                .required(Required::True),
+        );
+        operation.responses.insert(
+            "400",
+            salvo::oapi::Response::new("请求体不是json或者不符合结构").add_content(
+                APPLICATION_JSON.essence_str(),
+                Content::new(Response::<()>::to_schema(components)),
+            ),
+        );
+    }
+}
+
 pub fn logged(depot: &mut Depot) -> bool {
+    let epoch = depot.obtain::<SessionEpoch>().unwrap().get();
     depot
         .session()
-        .map(|session| session.get("logged"))
-        .and_then(identity)
+        .map(|session| {
+            session.get::<bool>("logged").unwrap_or_default()
+                && session.get::<u64>("epoch").unwrap_or_default() == epoch
+        })
         .unwrap_or_default()
 }
@@ -0,0 +1,154 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use salvo::{
+    Depot, FlowCtrl, Handler, Request, Router, async_trait,
+    http::{
+        HeaderValue, StatusCode,
+        header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, VARY},
+    },
+};
+
+/// SPA的入口, Modern.js默认输出到`html/main/index.html`
+const INDEX_FILES: [&str; 2] = ["index.html", "html/main/index.html"];
+
+/// 预压缩文件的后缀, 按优先级排列
+const ENCODINGS: [(&str, &str); 2] = [("br", ".br"), ("gzip", ".gz")];
+
+/// 构建好的前端文件, 挂载在`v1`接口之外的所有路径上
+pub enum Frontend {
+    Dir(PathBuf),
+    #[cfg(feature = "embed_frontend")]
+    Embedded,
+}
+
+#[cfg(feature = "embed_frontend")]
+#[derive(rust_embed::RustEmbed)]
+#[folder = "web/dist"]
+#[allow_missing = true]
+struct Assets;
+
+impl Frontend {
+    /// 优先使用`server.web_dir`指定的目录, 其次是编译进程序的文件, 最后是`./web/dist`
+    pub fn select(web_dir: Option<&Path>) -> Option<Frontend> {
+        if let Some(dir) = web_dir {
+            return Some(Frontend::Dir(dir.to_path_buf()));
+        }
+        #[cfg(feature = "embed_frontend")]
+        return Some(Frontend::Embedded);
+        #[cfg(not(feature = "embed_frontend"))]
+        {
+            let dir = PathBuf::from("./web/dist");
+            dir.is_dir().then_some(Frontend::Dir(dir))
+        }
+    }
+
+    pub fn router(self) -> Router {
+        Router::with_path("<**path>").get(FrontendHandler(Arc::new(self)))
+    }
+
+    async fn get(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        match self {
+            Frontend::Dir(root) => tokio::fs::read(root.join(path)).await.ok().map(Cow::Owned),
+            #[cfg(feature = "embed_frontend")]
+            Frontend::Embedded => Assets::get(path).map(|file| file.data),
+        }
+    }
+
+    async fn index(&self) -> Option<Cow<'static, [u8]>> {
+        for index in INDEX_FILES {
+            if let Some(data) = self.get(index).await {
+                return Some(data);
+            }
+        }
+        None
+    }
+}
+
+struct FrontendHandler(Arc<Frontend>);
+
+#[async_trait]
+impl Handler for FrontendHandler {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut salvo::Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let path = req.uri().path().trim_start_matches('/').to_owned();
+        // 未匹配的接口路径交给catcher返回json格式的404
+        if path.starts_with("v1/") || path.split('/').any(|seg| seg == ".." || seg.contains('\\')) {
+            res.status_code(StatusCode::NOT_FOUND);
+            return;
+        }
+
+        let file_name = path.rsplit('/').next().unwrap_or_default();
+        if !file_name.is_empty() {
+            let accept = req
+                .headers()
+                .get(ACCEPT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            for (encoding, suffix) in ENCODINGS {
+                if !accept_encoding(accept, encoding) {
+                    continue;
+                }
+                if let Some(data) = self.0.get(&format!("{path}{suffix}")).await {
+                    res.headers_mut()
+                        .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+                    res.headers_mut()
+                        .insert(VARY, HeaderValue::from_static("accept-encoding"));
+                    return write(res, &path, data);
+                }
+            }
+            if let Some(data) = self.0.get(&path).await {
+                return write(res, &path, data);
+            }
+        }
+
+        // 没有扩展名的路径视为前端路由, 返回入口页面
+        if !file_name.contains('.')
+            && let Some(data) = self.0.index().await
+        {
+            return write(res, "index.html", data);
+        }
+        res.status_code(StatusCode::NOT_FOUND);
+    }
+}
+
+fn write(res: &mut salvo::Response, path: &str, data: Cow<'static, [u8]>) {
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    let cache = if is_hashed(path) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    res.add_header(CONTENT_TYPE, mime.essence_str(), true).ok();
+    res.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static(cache));
+    res.write_body(data.into_owned()).ok();
+}
+
+fn accept_encoding(accept: &str, encoding: &str) -> bool {
+    accept.split(',').any(|item| {
+        let mut parts = item.split(';');
+        parts.next().is_some_and(|name| name.trim() == encoding)
+            && parts.all(|param| param.trim() != "q=0")
+    })
+}
+
+/// 文件名中带有内容哈希的文件, 例如`main.3f2a1b9c.js`, 可以永久缓存
+fn is_hashed(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or_default();
+    let parts = file_name.split('.').collect::<Vec<_>>();
+    parts.len() >= 3
+        && parts[1..parts.len() - 1].iter().any(|part| {
+            part.len() >= 8
+                && part.chars().all(|c| c.is_ascii_alphanumeric())
+                && part.chars().any(|c| c.is_ascii_digit())
+        })
+}
@@ -1,47 +1,202 @@
-use std::time::Duration;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
+use frontend::Frontend;
 use resp::Response;
 use salvo::{
     Depot, FlowCtrl, Listener, Request, Router, Server, Service, affix_state,
     catcher::Catcher,
-    conn::TcpListener,
+    conn::{Acceptor, TcpListener},
     handler,
     server::ServerHandle,
     session::{CookieStore, SessionHandler},
 };
-use surrealdb::{Surreal, engine::any::Any};
+use tls::TlsConfig;
 use tokio::task::JoinHandle;
 
-use crate::db::{self, model::config::is_new_install};
+use crate::{
+    config::ServerConfig,
+    db::{
+        backup::Backups,
+        connection,
+        repo::{self, CachedRepo, DynRepo, MeteredRepo},
+    },
+    metrics::Metrics,
+    storage::{self, DynStorage},
+};
 
+mod access_log;
+mod conditional;
+mod cors;
 mod extractors;
+mod frontend;
+mod openapi;
+mod probe;
 mod resp;
+mod tls;
 mod v1;
 
-struct Installed;
+/// 保存会话的cookie名称
+pub(crate) const SESSION_COOKIE: &str = "bulog";
 
-pub async fn web_server() -> anyhow::Result<(ServerHandle, JoinHandle<()>)> {
-    let bind = std::env::var("BU_BIND").unwrap_or_else(|_| "0.0.0.0:8686".to_owned());
-    let db = db::db(None).await?;
-    let router = router(db).await?;
+/// 博客是否已经安装, 启动时从数据库读取一次, 之后只由安装接口更新,
+/// 所有请求共享同一份状态, 避免每个请求都去查询数据库
+#[derive(Clone, Default)]
+pub(crate) struct Installed(Arc<AtomicBool>);
+
+impl Installed {
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
 
-    tracing::info!("listen on {}", bind);
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
 
-    let listener = TcpListener::new(bind).bind().await;
-    let server = Server::new(listener);
+/// 当前的会话纪元, 与数据库中的值保持一致, 登录时写入会话, 修改密码后旧会话全部失效
+#[derive(Clone, Default)]
+pub(crate) struct SessionEpoch(Arc<AtomicU64>);
 
-    let server_handle = server.handle();
-    let join_handle = tokio::spawn(server.serve(Service::new(router).catcher(catcher())));
+impl SessionEpoch {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub fn set(&self, epoch: u64) {
+        self.0.store(epoch, Ordering::Release);
+    }
+}
+
+pub async fn web_server(config: &ServerConfig) -> anyhow::Result<(ServerHandle, JoinHandle<()>)> {
+    let server = &config.server;
+    let repo = repo::open(&config.database).await?;
+    let monitor = repo
+        .surreal()
+        .and_then(|db| connection::monitor(db.clone(), config.database.clone()));
+    let storage = storage::storage(&config.upload)?;
+    let backups = Backups::new(config.backup.clone());
+    let mut router =
+        Router::new().push(router(config, repo.clone(), storage.clone(), backups.clone()).await?);
+    match Frontend::select(server.web_dir.as_deref()) {
+        Some(frontend) => router = router.push(frontend.router()),
+        None => tracing::info!("frontend assets not found, only serving the api"),
+    }
+
+    let listener = TcpListener::new(server.bind.clone());
+    let (server_handle, join_handle) = match TlsConfig::from_config(server) {
+        None => {
+            tracing::info!("listen on http://{}", server.bind);
+            serve(listener.bind().await, service(config, router))
+        }
+        Some(TlsConfig::Files(files)) => {
+            tracing::info!("listen on https://{}", server.bind);
+            let listener = listener.rustls(files.into_stream()?);
+            serve_tls(listener, config, router).await
+        }
+        Some(TlsConfig::Acme(acme)) => {
+            tracing::info!("listen on https://{}", server.bind);
+            let listener = tls::apply_acme(acme, listener.acme(), &mut router);
+            serve_tls(listener, config, router).await
+        }
+    };
+
+    // 连接检查和定时备份随服务器一起停止
+    let schedule = repo
+        .surreal()
+        .and_then(|db| backups.schedule(db.clone(), storage));
+    let join_handle = tokio::spawn(async move {
+        join_handle.await.ok();
+        for task in monitor.into_iter().chain(schedule) {
+            task.abort();
+        }
+    });
     Ok((server_handle, join_handle))
 }
 
-pub(crate) async fn router(db: Surreal<Any>) -> anyhow::Result<Router> {
-    let session_handler = session_secret(&db).await?;
+async fn serve_tls<L>(
+    listener: L,
+    config: &ServerConfig,
+    router: Router,
+) -> (ServerHandle, JoinHandle<()>)
+where
+    L: Listener + Send + Unpin + 'static,
+    L::Acceptor: Send + Unpin + 'static,
+{
+    match &config.server.http_bind {
+        Some(http_bind) => {
+            tracing::info!("redirect http://{} to https", http_bind);
+            let acceptor = listener
+                .join(TcpListener::new(http_bind.clone()))
+                .bind()
+                .await;
+            let service = service(config, router).hoop(tls::force_https(config.port()));
+            serve(acceptor, service)
+        }
+        None => serve(listener.bind().await, service(config, router)),
+    }
+}
+
+fn serve<A>(acceptor: A, service: Service) -> (ServerHandle, JoinHandle<()>)
+where
+    A: Acceptor + Send + 'static,
+{
+    let server = Server::new(acceptor);
+    let server_handle = server.handle();
+    let join_handle = tokio::spawn(server.serve(service));
+    (server_handle, join_handle)
+}
+
+fn service(config: &ServerConfig, router: Router) -> Service {
+    let mut service = Service::new(router)
+        .catcher(catcher())
+        .hoop(access_log::access_log);
+    if let Some(compression) = conditional::compression(config.server.compression) {
+        service = service.hoop(compression);
+    }
+    service = service.hoop(conditional::conditional);
+    if let Some(cors) = cors::cors_handler(&config.server.cors) {
+        tracing::info!("cors enabled for {}", config.server.cors.origins.join(", "));
+        service = service.hoop(cors);
+    }
+    service
+}
+
+pub(crate) async fn router(
+    config: &ServerConfig,
+    repo: DynRepo,
+    storage: DynStorage,
+    backups: Backups,
+) -> anyhow::Result<Router> {
+    let metrics = Arc::new(Metrics::new(config.session.ttl()));
+    let repo = CachedRepo::wrap(MeteredRepo::wrap(repo, metrics.clone()), &config.cache);
+    let session_handler = session_handler(&repo.session_secret().await?, config.session.ttl())?;
+    let installed = Installed::default();
+    if !repo.is_new_install().await? {
+        installed.set();
+    }
+    let epoch = SessionEpoch::default();
+    epoch.set(repo.query_session_epoch().await?);
     Ok(Router::new()
+        .hoop(probe::RecordRequest(metrics.clone()))
         .hoop(session_handler)
-        .hoop(affix_state::inject(db))
-        .hoop(initialization_check)
-        .push(v1::router()))
+        .hoop(
+            affix_state::inject(repo)
+                .inject(storage)
+                .inject(installed)
+                .inject(epoch)
+                .inject(backups)
+                .inject(metrics),
+        )
+        .push(probe::router(config.server.metrics))
+        .push(v1::health::router())
+        .push(openapi::router(config.server.api_docs))
+        .push(Router::new().hoop(initialization_check).push(v1::router())))
 }
 
 pub(crate) fn catcher() -> Catcher {
@@ -54,60 +209,59 @@ async fn initialization_check(
     req: &mut Request,
     resp: &mut salvo::Response,
     depot: &mut Depot,
-) -> anyhow::Result<()> {
-    if !depot.contains::<Installed>() {
-        let db = depot.obtain::<Surreal<Any>>().unwrap();
-        let installed = !is_new_install(db).await?;
-
-        if !installed && !req.uri().path().ends_with("/install") {
-            resp.render(Response::custom(0, "uninitialized"));
-            ctrl.cease();
-        } else if installed {
-            depot.inject(Installed);
-        }
+) {
+    let installed = depot.obtain::<Installed>().unwrap();
+    if !installed.get() && !req.uri().path().ends_with("/install") {
+        resp.render(Response::custom(0, "uninitialized"));
+        ctrl.cease();
     }
-    Ok(())
 }
 
-async fn session_secret(db: &Surreal<Any>) -> anyhow::Result<SessionHandler<CookieStore>> {
-    let secret = db
-        .query(
-            "LET $secret = (SELECT session FROM ONLY secret:bulog).session; \
-            IF $secret != NONE { \
-                RETURN $secret; \
-            } ELSE { \
-                LET $secret = rand::string(64); \
-                CREATE secret:bulog SET session = $secret; \
-                RETURN $secret; \
-            }",
-        )
-        .await?
-        .take::<Option<String>>(1)?
-        .unwrap();
+fn session_handler(secret: &str, ttl: Duration) -> anyhow::Result<SessionHandler<CookieStore>> {
     SessionHandler::builder(CookieStore::new(), secret.as_bytes())
-        .cookie_name("bulog")
-        .session_ttl(Some(Duration::from_secs(30 * 3600 * 24)))
+        .cookie_name(SESSION_COOKIE)
+        .session_ttl(Some(ttl))
         .build()
         .map_err(Into::into)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use futures_util::StreamExt;
     use salvo::{
-        Service,
+        Router, Service,
+        conn::TcpListener,
         http::{
+            StatusCode,
             cookie::CookieJar,
-            header::{CONTENT_TYPE, COOKIE},
+            header::{
+                ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_ORIGIN,
+                ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_METHOD, CACHE_CONTROL,
+                CONTENT_ENCODING, CONTENT_TYPE, COOKIE, ETAG, HOST, IF_NONE_MATCH, LOCATION,
+                ORIGIN,
+            },
             mime,
         },
-        test::{ResponseExt, TestClient},
+        test::{RequestBuilder, ResponseExt, TestClient},
     };
     use serde::{Deserialize, Serialize};
     use serde_json::json;
+    use tracing_subscriber::{fmt::format::FmtSpan, util::SubscriberInitExt};
 
-    use crate::db::{db, test_db};
+    use crate::{
+        config::{AcmeChallenge, AcmeConfig, CorsConfig, ServerConfig},
+        db::{backup::Backups, repo::DynRepo, test_db, test_repos},
+        storage::{MemoryStorage, TempDir},
+    };
 
-    use super::catcher;
+    use super::{
+        catcher,
+        cors::cors_handler,
+        frontend::Frontend,
+        tls::{CertFiles, apply_acme, force_https},
+    };
 
     struct HttpClient {
         service: Service,
@@ -115,39 +269,74 @@ mod tests {
     }
 
     impl HttpClient {
-        pub async fn get(&self, uri: &str) -> Response {
-            TestClient::get(format!("http://localhost:0/{}", uri))
-                .add_header(
-                    COOKIE,
-                    &self
-                        .cookie
-                        .iter()
-                        .map(|c| c.encoded().to_string())
-                        .collect::<Vec<_>>()
-                        .join("; "),
-                    true,
-                )
-                .send(&self.service)
+        pub async fn get(&mut self, uri: &str) -> Response {
+            self.send(TestClient::get(format!("http://localhost:0/{}", uri)))
                 .await
-                .take_json()
-                .await
-                .unwrap()
         }
 
         pub async fn post<T>(&mut self, uri: &str, data: &T) -> Response
         where
             T: Serialize,
         {
-            let mut resp = TestClient::post(format!("http://localhost:0/{}", uri))
-                .add_header(CONTENT_TYPE, mime::APPLICATION_JSON.essence_str(), true)
-                .json(data)
+            self.send(json_body(
+                TestClient::post(format!("http://localhost:0/{}", uri)),
+                data,
+            ))
+            .await
+        }
+
+        pub async fn patch<T>(&mut self, uri: &str, data: &T) -> Response
+        where
+            T: Serialize,
+        {
+            self.send(json_body(
+                TestClient::patch(format!("http://localhost:0/{}", uri)),
+                data,
+            ))
+            .await
+        }
+
+        pub async fn delete(&mut self, uri: &str) -> Response {
+            self.send(TestClient::delete(format!("http://localhost:0/{}", uri)))
+                .await
+        }
+
+        pub async fn upload(&mut self, uri: &str, filename: &str, data: &[u8]) -> Response {
+            let mut body = format!(
+                "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+            )
+            .into_bytes();
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n--boundary--\r\n");
+            self.send(
+                TestClient::post(format!("http://localhost:0/{}", uri))
+                    .add_header(CONTENT_TYPE, "multipart/form-data; boundary=boundary", true)
+                    .bytes(body),
+            )
+            .await
+        }
+
+        async fn send(&mut self, req: RequestBuilder) -> Response {
+            self.send_raw(req).await.take_json().await.unwrap()
+        }
+
+        async fn send_raw(&mut self, req: RequestBuilder) -> salvo::Response {
+            let resp = req
+                .add_header(COOKIE, self.cookie_header(), true)
                 .send(&self.service)
                 .await;
             for cookie in resp.cookies().iter() {
                 self.cookie.add(cookie.clone());
-                println!("{:?}", cookie);
             }
-            resp.take_json().await.unwrap()
+            resp
+        }
+
+        fn cookie_header(&self) -> String {
+            self.cookie
+                .iter()
+                .map(|c| c.encoded().to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
         }
 
         pub fn new(service: Service) -> Self {
@@ -162,6 +351,11 @@ mod tests {
         }
     }
 
+    fn json_body<T: Serialize>(req: RequestBuilder, data: &T) -> RequestBuilder {
+        req.add_header(CONTENT_TYPE, mime::APPLICATION_JSON.essence_str(), true)
+            .json(data)
+    }
+
     #[derive(Deserialize)]
     struct Response {
         code: u16,
@@ -169,18 +363,39 @@ mod tests {
         data: serde_json::Value,
     }
 
+    /// 测试用的api路由, 媒体文件保存在内存中
+    async fn test_router(config: &ServerConfig, repo: DynRepo) -> Router {
+        super::router(
+            config,
+            repo,
+            Arc::new(MemoryStorage::default()),
+            Backups::new(config.backup.clone()),
+        )
+        .await
+        .unwrap()
+    }
+
     async fn service() -> Service {
-        Service::new(super::router(test_db().await.unwrap()).await.unwrap()).catcher(catcher())
+        Service::new(
+            test_router(&ServerConfig::default(), Arc::new(test_db().await.unwrap())).await,
+        )
+        .catcher(catcher())
     }
 
     #[tokio::test]
     async fn test_install() {
-        let service = Service::new(
-            super::router(db(Some("mem://".to_owned())).await.unwrap())
-                .await
-                .unwrap(),
-        );
+        for repo in test_repos().await.unwrap() {
+            install_and_login(repo).await;
+        }
+    }
+
+    async fn install_and_login(repo: DynRepo) {
+        let service = Service::new(test_router(&ServerConfig::default(), repo).await);
         let mut client = HttpClient::new(service);
+        let health = client.get("/v1/health").await;
+        assert_eq!(health.code, 200);
+        assert_eq!(health.data["database"], true);
+        assert_eq!(health.data["cache"]["config"]["entries"], 0);
         let notinstalled = client.get("/v1/config").await;
         assert_eq!(notinstalled.code, 0);
         assert_eq!(notinstalled.message, "uninitialized");
@@ -203,6 +418,20 @@ mod tests {
         assert_eq!(installed.data["title"], "new blog");
         assert_eq!(installed.data["description"], "an apple");
         assert_eq!(installed.data["password"], "");
+
+        let repeat = client
+            .post(
+                "/v1/install",
+                &json!({ "title": "", "description": "", "password": "" }),
+            )
+            .await;
+        assert_eq!(repeat.code, 409);
+        assert_eq!(repeat.message, "repeat installation");
+
+        // 安装时不会读取请求中的password
+        let login = client.post("/v1/login", &json!({ "password": "" })).await;
+        assert_eq!(login.code, 200);
+        assert_eq!(client.get("/v1/login").await.code, 200);
     }
 
     #[tokio::test]
@@ -224,4 +453,560 @@ mod tests {
         let logged = client.get("/v1/login").await;
         assert_eq!(logged.code, 200);
     }
+
+    #[tokio::test]
+    async fn test_update_config() {
+        let mut client = HttpClient::default().await;
+        let resp = client.patch("/v1/config", &json!({ "title": "t" })).await;
+        assert_eq!(resp.code, 403);
+
+        client.post("/v1/login", &json!({ "password": "" })).await;
+        let resp = client.patch("/v1/config", &json!({ "title": " " })).await;
+        assert_eq!(resp.code, 400);
+
+        let resp = client
+            .patch("/v1/config", &json!({ "title": "patched" }))
+            .await;
+        assert_eq!(resp.code, 200);
+        assert_eq!(resp.data["title"], "patched");
+        assert_eq!(resp.data["description"], "A sample blog program");
+
+        let resp = client
+            .patch("/v1/config", &json!({ "password": "new password" }))
+            .await;
+        assert_eq!(resp.code, 400);
+        let resp = client
+            .patch(
+                "/v1/config",
+                &json!({ "password": "new password", "current_password": "wrong" }),
+            )
+            .await;
+        assert_eq!(resp.code, 401);
+
+        let other_session = client.cookie.clone();
+        let resp = client
+            .patch(
+                "/v1/config",
+                &json!({ "password": "new password", "current_password": "" }),
+            )
+            .await;
+        assert_eq!(resp.code, 200);
+        assert_eq!(client.get("/v1/login").await.code, 200);
+
+        client.cookie = other_session;
+        assert_eq!(client.get("/v1/login").await.code, 403);
+        let resp = client
+            .post("/v1/login", &json!({ "password": "new password" }))
+            .await;
+        assert_eq!(resp.code, 200);
+        assert_eq!(client.get("/v1/login").await.code, 200);
+    }
+
+    #[tokio::test]
+    async fn test_media() {
+        let mut client = HttpClient::default().await;
+        // 只包含文件头的1x2 png
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+
+        let resp = client.upload("/v1/media", "a.png", &png).await;
+        assert_eq!(resp.code, 403);
+
+        client.post("/v1/login", &json!({ "password": "" })).await;
+        let resp = client.upload("/v1/media", "a.png", &png).await;
+        assert_eq!(resp.code, 200);
+        let media = &resp.data[0];
+        assert_eq!(media["mime"], "image/png");
+        assert_eq!(media["width"], 1);
+        assert_eq!(media["height"], 2);
+        assert_eq!(media["size"], png.len());
+        let id = media["id"].as_str().unwrap().to_owned();
+
+        let resp = client.upload("/v1/media", "b.png", &png).await;
+        assert_eq!(resp.data[0]["id"], id.as_str());
+
+        // 超过整个请求的上限, 不会读取请求体
+        let resp = client
+            .send_raw(
+                TestClient::post("http://localhost:0/v1/media")
+                    .add_header(CONTENT_TYPE, "multipart/form-data; boundary=boundary", true)
+                    .bytes(vec![0; (128 << 20) + 1]),
+            )
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::PAYLOAD_TOO_LARGE));
+        assert_eq!(
+            client.get("/v1/media").await.data.as_array().unwrap().len(),
+            1
+        );
+
+        let mut resp = client
+            .send_raw(TestClient::get(format!(
+                "http://localhost:0/v1/media/{id}/file"
+            )))
+            .await;
+        let etag = resp.headers().get(ETAG).unwrap().clone();
+        assert!(
+            resp.headers()[CACHE_CONTROL]
+                .to_str()
+                .unwrap()
+                .contains("immutable")
+        );
+        assert_eq!(resp.take_bytes(None).await.unwrap(), png);
+
+        let resp = client
+            .send_raw(
+                TestClient::get(format!("http://localhost:0/v1/media/{id}/file")).add_header(
+                    IF_NONE_MATCH,
+                    etag,
+                    true,
+                ),
+            )
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::NOT_MODIFIED));
+
+        assert_eq!(client.delete(&format!("/v1/media/{id}")).await.code, 200);
+        assert_eq!(client.get(&format!("/v1/media/{id}/file")).await.code, 404);
+    }
+
+    #[tokio::test]
+    async fn test_media_variants() {
+        let mut client = HttpClient::default().await;
+        client.post("/v1/login", &json!({ "password": "" })).await;
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(700, 70)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let resp = client.upload("/v1/media", "wide.png", png.get_ref()).await;
+        let media = &resp.data[0];
+        let id = media["id"].as_str().unwrap().to_owned();
+        let widths = media["srcset"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["width"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(widths, [320, 640, 700]);
+        assert_eq!(
+            media["srcset"][0]["url"],
+            format!("/v1/media/{id}/file?w=320")
+        );
+
+        let mut resp = client
+            .send_raw(TestClient::get(format!(
+                "http://localhost:0/v1/media/{id}/file?w=320"
+            )))
+            .await;
+        assert_eq!(resp.headers()[CONTENT_TYPE], "image/webp");
+        let webp = resp.take_bytes(None).await.unwrap();
+        let size = imagesize::blob_size(&webp).unwrap();
+        assert_eq!((size.width, size.height), (320, 32));
+
+        let resp = client.get(&format!("/v1/media/{id}/file?w=500")).await;
+        assert_eq!(resp.code, 400);
+    }
+
+    #[tokio::test]
+    async fn test_backup() {
+        let mut config = ServerConfig::default();
+        let dir = TempDir::new("bulog-web-backup");
+        config.backup.dir = dir.to_path_buf();
+        let service = Service::new(test_router(&config, Arc::new(test_db().await.unwrap())).await);
+        let mut client = HttpClient::new(service);
+        assert_eq!(client.post("/v1/backup", &json!({})).await.code, 403);
+
+        client.post("/v1/login", &json!({ "password": "" })).await;
+        let resp = client.post("/v1/backup", &json!({})).await;
+        assert_eq!(resp.code, 200);
+        assert_eq!(resp.data["tables"]["config"], 1);
+        let name = resp.data["name"].as_str().unwrap().to_owned();
+        assert!(
+            config
+                .backup
+                .dir
+                .join(&name)
+                .join("manifest.json")
+                .is_file()
+        );
+
+        let resp = client.get("/v1/backup").await;
+        assert_eq!(resp.data[0]["name"], name.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_frontend() {
+        let dir = TempDir::new("bulog-web");
+        std::fs::create_dir_all(dir.join("static/js")).unwrap();
+        std::fs::write(dir.join("index.html"), "<html></html>").unwrap();
+        std::fs::write(dir.join("static/js/main.1a2b3c4d.js"), "js").unwrap();
+        std::fs::write(dir.join("static/js/main.1a2b3c4d.js.br"), "br").unwrap();
+
+        let router = Router::new()
+            .push(test_router(&ServerConfig::default(), Arc::new(test_db().await.unwrap())).await)
+            .push(Frontend::Dir(dir.to_path_buf()).router());
+        let mut client = HttpClient::new(Service::new(router).catcher(catcher()));
+
+        let mut resp = client
+            .send_raw(TestClient::get("http://localhost:0/posts/abc"))
+            .await;
+        assert_eq!(resp.headers()[CONTENT_TYPE], "text/html");
+        assert_eq!(resp.headers()[CACHE_CONTROL], "no-cache");
+        assert_eq!(resp.take_string().await.unwrap(), "<html></html>");
+
+        let mut resp = client
+            .send_raw(
+                TestClient::get("http://localhost:0/static/js/main.1a2b3c4d.js").add_header(
+                    ACCEPT_ENCODING,
+                    "gzip, br",
+                    true,
+                ),
+            )
+            .await;
+        assert_eq!(resp.headers()[CONTENT_ENCODING], "br");
+        assert!(
+            resp.headers()[CACHE_CONTROL]
+                .to_str()
+                .unwrap()
+                .contains("immutable")
+        );
+        assert_eq!(resp.take_bytes(None).await.unwrap(), "br");
+
+        let resp = client
+            .send_raw(TestClient::get("http://localhost:0/static/js/missing.js"))
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::NOT_FOUND));
+        assert_eq!(client.get("/v1/missing").await.code, 404);
+        assert_eq!(client.get("/v1/config").await.code, 200);
+    }
+
+    #[tokio::test]
+    async fn test_conditional() {
+        let router = Router::new()
+            .push(test_router(&ServerConfig::default(), Arc::new(test_db().await.unwrap())).await);
+        let mut client = HttpClient::new(super::service(&ServerConfig::default(), router));
+
+        let resp = client
+            .send_raw(TestClient::get("http://localhost:0/v1/config"))
+            .await;
+        let etag = resp.headers()[ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with("W/\""));
+        assert_eq!(resp.headers()[CACHE_CONTROL], "private, no-cache");
+
+        let mut resp = client
+            .send_raw(
+                TestClient::get("http://localhost:0/v1/config")
+                    .add_header(IF_NONE_MATCH, etag.clone(), true)
+                    .add_header(ACCEPT_ENCODING, "gzip", true),
+            )
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::NOT_MODIFIED));
+        assert!(resp.take_bytes(None).await.unwrap().is_empty());
+
+        for encoding in ["zstd", "br", "gzip"] {
+            let mut resp = client
+                .send_raw(TestClient::get("http://localhost:0/v1/config").add_header(
+                    ACCEPT_ENCODING,
+                    encoding,
+                    true,
+                ))
+                .await;
+            assert_eq!(resp.headers()[CONTENT_ENCODING], encoding);
+            assert_eq!(resp.headers()[ETAG], etag);
+            let config: Response =
+                serde_json::from_str(&resp.take_string().await.unwrap()).unwrap();
+            assert_eq!(config.data["title"], "bulog");
+        }
+
+        client.post("/v1/login", &json!({ "password": "" })).await;
+        let changed = client
+            .patch("/v1/config", &json!({ "title": "changed" }))
+            .await;
+        assert_eq!(changed.code, 200);
+        let resp = client
+            .send_raw(TestClient::get("http://localhost:0/v1/config").add_header(
+                IF_NONE_MATCH,
+                etag.clone(),
+                true,
+            ))
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::OK));
+        assert_ne!(resp.headers()[ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn test_probes() {
+        let mut config = ServerConfig::default();
+        config.server.metrics = true;
+        let service = Service::new(
+            test_router(
+                &config,
+                Arc::new(crate::db::db(Some("mem://".to_owned())).await.unwrap()),
+            )
+            .await,
+        )
+        .catcher(catcher());
+        let mut client = HttpClient::new(service);
+
+        // 安装前探针也可以使用
+        let mut resp = client
+            .send_raw(TestClient::get("http://localhost:0/healthz"))
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::OK));
+        assert_eq!(resp.take_string().await.unwrap(), "ok");
+        let mut resp = client
+            .send_raw(TestClient::get("http://localhost:0/readyz"))
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::OK));
+        assert!(
+            resp.take_string()
+                .await
+                .unwrap()
+                .contains("installed: false")
+        );
+
+        assert_eq!(client.get("/v1/config").await.message, "uninitialized");
+        client
+            .post(
+                "/v1/install",
+                &json!({ "title": "blog", "description": "" }),
+            )
+            .await;
+        assert_eq!(client.get("/v1/config").await.code, 200);
+        client.get("/v1/media/abc/file").await;
+        client
+            .post("/v1/login", &json!({ "password": "wrong" }))
+            .await;
+        client.post("/v1/login", &json!({ "password": "" })).await;
+
+        let mut resp = client
+            .send_raw(TestClient::get("http://localhost:0/metrics"))
+            .await;
+        assert!(
+            resp.headers()[CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain")
+        );
+        let metrics = resp.take_string().await.unwrap();
+        assert!(metrics.contains(
+            "bulog_http_request_duration_seconds_count{method=\"GET\",route=\"/v1/config\",status=\"200\"} 2\n"
+        ));
+        assert!(metrics.contains("route=\"/v1/media/{id}/file\""));
+        assert!(metrics.contains(
+            "bulog_db_query_duration_seconds_count{operation=\"create_config\",result=\"ok\"} 1\n"
+        ));
+        assert!(metrics.contains("bulog_logins_total{result=\"failure\"} 1\n"));
+        assert!(metrics.contains("bulog_installed 1\n"));
+        assert!(metrics.contains("bulog_active_sessions 1\n"));
+        assert!(
+            metrics.contains("bulog_cache_requests_total{section=\"config\",result=\"miss\"} 1\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        #[derive(Clone, Default)]
+        struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let _guard = tracing_subscriber::fmt()
+            .json()
+            .with_env_filter("bulog=info")
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(move || writer.clone())
+            .finish()
+            .set_default();
+
+        let router = Router::new()
+            .push(test_router(&ServerConfig::default(), Arc::new(test_db().await.unwrap())).await);
+        let mut client = HttpClient::new(super::service(&ServerConfig::default(), router));
+
+        let resp = client
+            .send_raw(TestClient::get("http://localhost:0/v1/config").add_header(
+                "x-request-id",
+                "req-1",
+                true,
+            ))
+            .await;
+        assert_eq!(resp.headers()["x-request-id"], "req-1");
+
+        let resp = client
+            .send_raw(TestClient::get("http://localhost:0/v1/config").add_header(
+                "x-request-id",
+                "bad id",
+                true,
+            ))
+            .await;
+        let generated = resp.headers()["x-request-id"].to_str().unwrap();
+        assert_eq!(generated.len(), 16);
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let lines = logs
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let completed = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "request completed")
+            .unwrap();
+        assert_eq!(completed["fields"]["status"], 200);
+        assert_eq!(completed["span"]["id"], "req-1");
+        assert_eq!(completed["span"]["method"], "GET");
+        assert_eq!(completed["span"]["path"], "/v1/config");
+        // 数据库操作的span在请求的span之内
+        let query = lines
+            .iter()
+            .find(|line| line["span"]["name"] == "query_config")
+            .unwrap();
+        assert_eq!(query["spans"][0]["id"], "req-1");
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let cors = CorsConfig {
+            origins: vec!["http://localhost:8080/".to_owned()],
+            credentials: true,
+            max_age: 600,
+        };
+        let service = Service::new(
+            test_router(
+                &ServerConfig::default(),
+                Arc::new(crate::db::db(Some("mem://".to_owned())).await.unwrap()),
+            )
+            .await,
+        )
+        .catcher(catcher())
+        .hoop(cors_handler(&cors).unwrap());
+        let mut client = HttpClient::new(service);
+
+        let resp = client
+            .send_raw(
+                TestClient::options("http://localhost:0/v1/config")
+                    .add_header(ORIGIN, "http://localhost:8080", true)
+                    .add_header(ACCESS_CONTROL_REQUEST_METHOD, "PATCH", true),
+            )
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::NO_CONTENT));
+        assert_eq!(
+            resp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:8080"
+        );
+        assert_eq!(resp.headers()[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(resp.headers()[ACCESS_CONTROL_MAX_AGE], "600");
+
+        let mut resp = client
+            .send_raw(TestClient::get("http://localhost:0/v1/config").add_header(
+                ORIGIN,
+                "http://localhost:8080",
+                true,
+            ))
+            .await;
+        assert_eq!(
+            resp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:8080"
+        );
+        assert_eq!(resp.take_json::<Response>().await.unwrap().code, 0);
+
+        let resp = client
+            .send_raw(TestClient::get("http://localhost:0/v1/config").add_header(
+                ORIGIN,
+                "http://evil.example",
+                true,
+            ))
+            .await;
+        assert!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let dir = TempDir::new("bulog-tls");
+        std::fs::create_dir_all(&dir).unwrap();
+        let write_keycert = || {
+            let keycert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+            std::fs::write(dir.join("cert.pem"), keycert.cert.pem()).unwrap();
+            std::fs::write(dir.join("key.pem"), keycert.key_pair.serialize_pem()).unwrap();
+        };
+        let files = || CertFiles {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+            interval: Duration::from_millis(50),
+        };
+
+        std::fs::write(dir.join("cert.pem"), "").unwrap();
+        std::fs::write(dir.join("key.pem"), "").unwrap();
+        assert!(files().into_stream().is_err());
+
+        write_keycert();
+        let mut stream = files().into_stream().unwrap();
+        assert!(stream.next().await.is_some());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        write_keycert();
+        tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // 不会真的去申请证书, 只检查HTTP-01的验证路由被注册并且不会被重定向
+        async fn http(challenge: AcmeChallenge) -> HttpClient {
+            let mut router = Router::new().push(
+                test_router(&ServerConfig::default(), Arc::new(test_db().await.unwrap())).await,
+            );
+            let acme = AcmeConfig {
+                domains: vec!["localhost".to_owned()],
+                challenge,
+                ..Default::default()
+            };
+            apply_acme(&acme, TcpListener::new("127.0.0.1:0").acme(), &mut router);
+            HttpClient::new(
+                Service::new(router)
+                    .catcher(catcher())
+                    .hoop(force_https(8443)),
+            )
+        }
+        let mut client = http(AcmeChallenge::Http01).await;
+        let resp = client
+            .send_raw(
+                TestClient::get("http://localhost:8686/v1/config?a=1").add_header(
+                    HOST,
+                    "localhost:8686",
+                    true,
+                ),
+            )
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::PERMANENT_REDIRECT));
+        assert_eq!(
+            resp.headers()[LOCATION],
+            "https://localhost:8443/v1/config?a=1"
+        );
+        // 未知的token由salvo原样返回
+        let mut resp = client
+            .send_raw(
+                TestClient::get("http://localhost:8686/.well-known/acme-challenge/token")
+                    .add_header(HOST, "localhost:8686", true),
+            )
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::OK));
+        assert_eq!(resp.take_string().await.unwrap(), "token");
+
+        let resp = http(AcmeChallenge::TlsAlpn01)
+            .await
+            .send_raw(
+                TestClient::get("http://localhost:8686/.well-known/acme-challenge/token")
+                    .add_header(HOST, "localhost:8686", true),
+            )
+            .await;
+        assert_eq!(resp.status_code, Some(StatusCode::NOT_FOUND));
+    }
 }
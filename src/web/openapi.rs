@@ -0,0 +1,108 @@
+use salvo::{
+    Router,
+    oapi::{
+        OpenApi, SecurityScheme,
+        scalar::Scalar,
+        security::{ApiKey, ApiKeyValue},
+    },
+};
+
+use super::{SESSION_COOKIE, v1};
+
+/// 接口文档的地址
+const SPEC_PATH: &str = "v1/openapi.json";
+
+/// 从`v1`的路由生成接口文档, 需要登录的接口使用`session`验证
+pub fn document() -> OpenApi {
+    OpenApi::new("bulog", env!("CARGO_PKG_VERSION"))
+        .add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE))),
+        )
+        .merge_router(&Router::new().push(v1::health::router()).push(v1::router()))
+}
+
+/// 不经过安装检查, `ui`为true时在`/v1/docs`提供Scalar页面
+pub fn router(ui: bool) -> Router {
+    let router = Router::new().push(document().into_router(SPEC_PATH));
+    if ui {
+        router.push(
+            Scalar::new(format!("/{SPEC_PATH}"))
+                .title("bulog api")
+                .into_router("v1/docs"),
+        )
+    } else {
+        router
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use salvo::{
+        Service,
+        http::StatusCode,
+        test::{ResponseExt, TestClient},
+    };
+
+    use super::{document, router};
+
+    /// 修改接口后设置`BU_UPDATE_OPENAPI=1`运行测试重新生成
+    #[tokio::test]
+    async fn test_openapi() -> anyhow::Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("docs/openapi.json");
+        let spec = document().to_pretty_json()? + "\n";
+        if std::env::var_os("BU_UPDATE_OPENAPI").is_some() {
+            std::fs::write(&path, &spec)?;
+        }
+        assert!(
+            std::fs::read_to_string(&path).is_ok_and(|saved| saved == spec),
+            "the api changed, regenerate docs/openapi.json with BU_UPDATE_OPENAPI=1"
+        );
+
+        let service = Service::new(router(true));
+        let mut res = TestClient::get("http://localhost:0/v1/openapi.json")
+            .send(&service)
+            .await;
+        let served: serde_json::Value = res.take_json().await?;
+        assert_eq!(served, serde_json::from_str::<serde_json::Value>(&spec)?);
+        let paths = served["paths"].as_object().unwrap();
+        for path in [
+            "/v1/health",
+            "/v1/install",
+            "/v1/config",
+            "/v1/login",
+            "/v1/media",
+            "/v1/media/{id}",
+            "/v1/media/{id}/file",
+            "/v1/backup",
+        ] {
+            assert!(paths.contains_key(path), "{path} is not documented");
+        }
+        let config = &served["paths"]["/v1/config"]["patch"];
+        assert_eq!(
+            config["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ConfigUpdate"
+        );
+        let envelope = &config["responses"]["200"]["content"]["application/json"]["schema"];
+        assert_eq!(
+            envelope["required"],
+            serde_json::json!(["code", "message", "data"])
+        );
+        assert_eq!(
+            envelope["properties"]["data"]["$ref"],
+            "#/components/schemas/ConfigRecord"
+        );
+
+        let res = TestClient::get("http://localhost:0/v1/docs")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let res = TestClient::get("http://localhost:0/v1/docs")
+            .send(&Service::new(router(false)))
+            .await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use std::{sync::Arc, time::Instant};
+
+use salvo::{
+    Depot, FlowCtrl, Handler, Request, Router, async_trait, handler,
+    http::{HeaderValue, StatusCode, header::CONTENT_TYPE},
+};
+
+use super::{Installed, SessionEpoch};
+use crate::{
+    db::repo::DynRepo,
+    metrics::{Metrics, write_gauge},
+};
+
+/// 给编排系统使用的探针, 不经过安装检查, 返回真实的http状态码
+pub fn router(metrics: bool) -> Router {
+    let router = Router::new()
+        .push(Router::with_path("healthz").get(healthz))
+        .push(Router::with_path("readyz").get(readyz));
+    if metrics {
+        router.push(Router::with_path("metrics").get(export))
+    } else {
+        router
+    }
+}
+
+/// 进程能处理请求就返回成功
+#[handler]
+async fn healthz(res: &mut salvo::Response) {
+    res.render("ok");
+}
+
+/// 数据库可用时才能接收流量
+#[handler]
+async fn readyz(depot: &mut Depot, res: &mut salvo::Response) {
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let installed = depot.obtain::<Installed>().unwrap().get();
+    if repo.health().await {
+        res.render(format!("database: ok\ninstalled: {installed}\n"));
+    } else {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        res.render(format!("database: unreachable\ninstalled: {installed}\n"));
+    }
+}
+
+#[handler]
+async fn export(depot: &mut Depot, res: &mut salvo::Response) {
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let mut out = String::new();
+    depot.obtain::<Arc<Metrics>>().unwrap().render(&mut out);
+    write_gauge(
+        &mut out,
+        "bulog_session_epoch",
+        "Current session epoch, bumped when the password changes",
+        depot.obtain::<SessionEpoch>().unwrap().get(),
+    );
+    write_gauge(
+        &mut out,
+        "bulog_installed",
+        "Whether the blog has been installed",
+        u8::from(depot.obtain::<Installed>().unwrap().get()),
+    );
+    if let Some(cache) = repo.cache() {
+        let stats = cache.stats();
+        out.push_str("# HELP bulog_cache_requests_total Cache lookups by section\n");
+        out.push_str("# TYPE bulog_cache_requests_total counter\n");
+        for (section, stats) in stats.sections() {
+            out.push_str(&format!(
+                "bulog_cache_requests_total{{section=\"{section}\",result=\"hit\"}} {}\n\
+                 bulog_cache_requests_total{{section=\"{section}\",result=\"miss\"}} {}\n",
+                stats.hits, stats.misses
+            ));
+        }
+        out.push_str("# HELP bulog_cache_entries Cached entries by section\n");
+        out.push_str("# TYPE bulog_cache_entries gauge\n");
+        for (section, stats) in stats.sections() {
+            out.push_str(&format!(
+                "bulog_cache_entries{{section=\"{section}\"}} {}\n",
+                stats.entries
+            ));
+        }
+    }
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+    );
+    res.render(out);
+}
+
+/// 记录每个请求的耗时, 路径中的参数替换为参数名, 避免标签数量无限增长
+pub struct RecordRequest(pub Arc<Metrics>);
+
+#[async_trait]
+impl Handler for RecordRequest {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut salvo::Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let start = Instant::now();
+        ctrl.call_next(req, depot, res).await;
+        let route = route(req);
+        self.0.observe_request(
+            req.method().as_str(),
+            &route,
+            res.status_code.unwrap_or(StatusCode::OK).as_u16(),
+            start.elapsed(),
+        );
+    }
+}
+
+fn route(req: &Request) -> String {
+    let mut route = String::new();
+    for segment in req.uri().path().split('/').filter(|s| !s.is_empty()) {
+        route.push('/');
+        match req.params().iter().find(|(_, value)| *value == segment) {
+            Some((name, _)) => route.push_str(&format!("{{{name}}}")),
+            None => route.push_str(segment),
+        }
+    }
+    if route.is_empty() {
+        route.push('/');
+    }
+    route
+}
@@ -1,5 +1,14 @@
-use salvo::http::{header::CONTENT_TYPE, mime::APPLICATION_JSON};
+use salvo::{
+    http::{header::CONTENT_TYPE, mime::APPLICATION_JSON},
+    oapi::{
+        BasicType, Components, Content, EndpointOutRegister, Object, Operation, RefOr, Schema,
+        ToSchema,
+    },
+};
 use serde::Serialize;
+use surrealdb::error::Db;
+
+use crate::db::model::config::RepeatInstallation;
 
 pub type RespResult<T> = Result<Response<T>, Response<()>>;
 
@@ -54,7 +63,31 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Response::error(err.into().to_string())
+        let err = err.into();
+        if err.is::<RepeatInstallation>() {
+            return Response::custom(409, err.to_string());
+        }
+        #[cfg(feature = "sqlite_backend")]
+        if let Some(invalid) = err.downcast_ref::<crate::db::repo::InvalidField>() {
+            return Response::custom(400, invalid.to_string());
+        }
+        let db_err = err.chain().find_map(|err| match err.downcast_ref() {
+            Some(surrealdb::Error::Db(db_err)) => Some(db_err),
+            _ => None,
+        });
+        match db_err {
+            // 违反表结构中的类型和断言是请求的数据有问题
+            Some(Db::FieldCheck { field, .. } | Db::FieldValue { field, .. }) => {
+                Response::custom(400, format!("invalid value for field `{field}`"))
+            }
+            Some(Db::FieldUndefined { field, .. }) => {
+                Response::custom(400, format!("unknown field `{field}`"))
+            }
+            Some(db_err @ (Db::IndexExists { .. } | Db::RecordExists { .. })) => {
+                Response::custom(409, db_err.to_string())
+            }
+            _ => Response::error(err.to_string()),
+        }
     }
 }
 
@@ -73,3 +106,194 @@ where
         }
     }
 }
+
+/// 文档中的响应结构, 和`data`的类型一起内联到每个接口中
+impl<T> ToSchema for Response<T>
+where
+    T: ToSchema,
+{
+    fn to_schema(components: &mut Components) -> RefOr<Schema> {
+        Object::new()
+            .property(
+                "code",
+                Object::new()
+                    .schema_type(BasicType::Integer)
+                    .description("与http状态码含义相同, 不是200时表示请求失败"),
+            )
+            .required("code")
+            .property(
+                "message",
+                Object::new()
+                    .schema_type(BasicType::String)
+                    .description("失败的原因, 成功时为空"),
+            )
+            .required("message")
+            .property("data", T::to_schema(components))
+            .required("data")
+            .into()
+    }
+}
+
+/// 接口总是以http 200返回`Response`, 失败时`data`为`null`,
+/// 所以`RespResult`中成功的类型先注册, 错误的类型不会覆盖它
+impl<T> EndpointOutRegister for Response<T>
+where
+    T: ToSchema,
+{
+    fn register(components: &mut Components, operation: &mut Operation) {
+        if operation.responses.contains_key("200") {
+            return;
+        }
+        operation.responses.insert(
+            "200",
+            salvo::oapi::Response::new("`code`为200时`data`是请求的结果").add_content(
+                APPLICATION_JSON.essence_str(),
+                Content::new(Self::to_schema(components)),
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{
+        model::{
+            comment::NewComment,
+            config::{ConfigRecord, ConfigRecordOption},
+            media::NewMedia,
+            post::{NewPost, PostRecordOption},
+        },
+        test_db, test_repos,
+    };
+
+    use super::Response;
+
+    #[tokio::test]
+    async fn test_schema_errors() -> anyhow::Result<()> {
+        let db = test_db().await?;
+        let err = db
+            .query("UPDATE config:bulog SET title = ''")
+            .await?
+            .check()
+            .unwrap_err();
+        assert_eq!(Response::from(err).code, 400);
+
+        // 未定义的字段不会被写入
+        db.query("UPDATE config:bulog SET junk = 1")
+            .await?
+            .check()?;
+        let junk: Option<i64> = db
+            .query("RETURN (SELECT junk FROM ONLY config:bulog).junk")
+            .await?
+            .take(0)?;
+        assert_eq!(junk, None);
+
+        db.query("CREATE media SET filename = 'a', mime = 'text/plain', size = 1, hash = 'h'")
+            .await?
+            .check()?;
+        let err = db
+            .query("CREATE media SET filename = 'b', mime = 'text/plain', size = 1, hash = 'h'")
+            .await?
+            .check()
+            .unwrap_err();
+        assert_eq!(Response::from(err).code, 409);
+
+        assert_eq!(Response::from(anyhow::anyhow!("other")).code, 500);
+        Ok(())
+    }
+
+    /// 通过`Repository`写入不合法的数据, 所有后端都返回400
+    #[tokio::test]
+    async fn test_repo_schema_errors() -> anyhow::Result<()> {
+        for repo in test_repos().await? {
+            repo.create_config(ConfigRecord::default()).await?;
+            // 重复安装是冲突而不是服务器错误
+            let err = repo
+                .create_config(ConfigRecord::default())
+                .await
+                .unwrap_err();
+            assert_eq!(Response::from(err).code, 409);
+
+            let err = repo
+                .update_config(ConfigRecordOption {
+                    title: Some(" ".to_owned()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(Response::from(err).code, 400);
+
+            let invalid_posts = [
+                NewPost {
+                    title: "".into(),
+                    ..Default::default()
+                },
+                NewPost {
+                    title: "a".repeat(257).into(),
+                    ..Default::default()
+                },
+                NewPost {
+                    title: "post".into(),
+                    tags: vec!["".into()],
+                    ..Default::default()
+                },
+                NewPost {
+                    title: "post".into(),
+                    tags: (0..65).map(|i| i.to_string().into()).collect(),
+                    ..Default::default()
+                },
+            ];
+            for post in invalid_posts {
+                let err = repo.insert_post(post).await.unwrap_err();
+                assert_eq!(Response::from(err).code, 400);
+            }
+            let id = repo
+                .insert_post(NewPost {
+                    title: "post".into(),
+                    ..Default::default()
+                })
+                .await?;
+            let err = repo
+                .update_post(
+                    id.clone(),
+                    PostRecordOption {
+                        title: Some("".into()),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(Response::from(err).code, 400);
+            assert_eq!(repo.query_post(id.clone()).await?.unwrap().title, "post");
+
+            let err = repo
+                .create_media(NewMedia {
+                    filename: "".into(),
+                    mime: "text/plain".into(),
+                    size: 1,
+                    width: None,
+                    height: None,
+                    hash: "h".into(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(Response::from(err).code, 400);
+
+            let err = repo
+                .create_comment(NewComment {
+                    post: id,
+                    parent: None,
+                    author: "".into(),
+                    email: "".into(),
+                    url: "".into(),
+                    content: " ".into(),
+                    created_time: None,
+                    approved: false,
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(Response::from(err).code, 400);
+        }
+        Ok(())
+    }
+}
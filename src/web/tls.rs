@@ -0,0 +1,139 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::{
+    StreamExt,
+    stream::{self, BoxStream},
+};
+use salvo::{
+    Depot, Request, Router,
+    conn::{
+        acme::AcmeListener,
+        rustls::{Keycert, RustlsConfig, ServerConfig},
+    },
+    handler::Skipper,
+    prelude::ForceHttps,
+};
+
+use crate::config::{AcmeChallenge, AcmeConfig, ListenConfig};
+
+/// 检查证书文件是否被更新的间隔
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// ACME的HTTP-01验证路径, 不能被重定向到https
+const ACME_CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+pub enum TlsConfig<'a> {
+    /// 手动管理的证书, 文件更新后自动重新加载
+    Files(CertFiles),
+    Acme(&'a AcmeConfig),
+}
+
+pub struct CertFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub interval: Duration,
+}
+
+impl TlsConfig<'_> {
+    /// 都没有配置时不启用tls, 两者互斥已经在加载配置时检查过了
+    pub fn from_config(config: &ListenConfig) -> Option<TlsConfig<'_>> {
+        if let Some(files) = &config.tls {
+            return Some(TlsConfig::Files(CertFiles {
+                cert: files.cert.clone(),
+                key: files.key.clone(),
+                interval: RELOAD_INTERVAL,
+            }));
+        }
+        config.acme.as_ref().map(TlsConfig::Acme)
+    }
+}
+
+impl CertFiles {
+    pub fn load(&self) -> anyhow::Result<RustlsConfig> {
+        let keycert = Keycert::new()
+            .cert_from_path(&self.cert)?
+            .key_from_path(&self.key)?;
+        let config = RustlsConfig::new(keycert);
+        // 提前构建一次, 文件无法解析时直接报错, 而不是等到握手时才发现
+        let _: ServerConfig = config.clone().try_into()?;
+        Ok(config)
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path| {
+            std::fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .ok()
+        };
+        Some((modified(&self.cert)?, modified(&self.key)?))
+    }
+
+    /// 首次加载失败时返回错误, 之后的重新加载失败只记录日志并继续使用旧证书
+    pub fn into_stream(self) -> anyhow::Result<BoxStream<'static, RustlsConfig>> {
+        let first = self.load()?;
+        let modified = self.modified();
+        let reloads = stream::unfold((self, modified), |(files, mut modified)| async move {
+            loop {
+                tokio::time::sleep(files.interval).await;
+                let current = files.modified();
+                if current.is_none() || current == modified {
+                    continue;
+                }
+                modified = current;
+                match files.load() {
+                    Ok(config) => {
+                        tracing::info!("tls certificate reloaded");
+                        return Some((config, (files, modified)));
+                    }
+                    Err(err) => tracing::warn!("reload tls certificate failed: {err:#}"),
+                }
+            }
+        });
+        Ok(stream::once(async { first }).chain(reloads).boxed())
+    }
+}
+
+pub fn apply_acme<T>(
+    config: &AcmeConfig,
+    listener: AcmeListener<T>,
+    router: &mut Router,
+) -> AcmeListener<T> {
+    let contacts = config
+        .contacts
+        .iter()
+        .map(|contact| {
+            if contact.starts_with("mailto:") {
+                contact.clone()
+            } else {
+                format!("mailto:{contact}")
+            }
+        })
+        .collect::<Vec<_>>();
+    let listener = listener
+        .get_directory("bulog", &config.directory)
+        .cache_path(&config.cache)
+        .domains(config.domains.clone())
+        .contacts(contacts);
+    match config.challenge {
+        AcmeChallenge::Http01 => listener.http01_challenge(router),
+        AcmeChallenge::TlsAlpn01 => listener.tls_alpn01_challenge(),
+    }
+}
+
+/// 把http请求重定向到`https_port`, ACME的验证请求除外
+pub fn force_https(https_port: u16) -> ForceHttps {
+    ForceHttps::new()
+        .https_port(https_port)
+        .skipper(SkipAcmeChallenge)
+}
+
+struct SkipAcmeChallenge;
+
+impl Skipper for SkipAcmeChallenge {
+    fn skipped(&self, req: &mut Request, _depot: &Depot) -> bool {
+        req.uri().path().starts_with(ACME_CHALLENGE_PATH)
+    }
+}
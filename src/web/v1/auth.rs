@@ -1,12 +1,17 @@
+use std::sync::Arc;
+
 use salvo::{
-    Depot, Router, Writer, handler,
+    Depot, Router, Writer,
+    oapi::{ToSchema, endpoint},
     session::{Session, SessionDepotExt},
 };
 use serde::Deserialize;
 
 use crate::{
-    db::model::config::verify_password,
+    db::repo::DynRepo,
+    metrics::Metrics,
     web::{
+        SessionEpoch,
         extractors::{Json, logged},
         resp::{RespResult, Response},
     },
@@ -19,23 +24,29 @@ pub fn router() -> Router {
         .post(login)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+#[salvo(schema(name = "LoginPost"))]
 pub struct LoginPost {
     pub password: String,
 }
 
-#[handler]
+/// 登录, 成功后设置会话cookie
+#[endpoint(tags("auth"))]
 async fn login(json: Json<LoginPost>, depot: &mut Depot) -> RespResult<()> {
     if logged(depot) {
         return Ok(Response::empty());
     }
 
     let Json(json) = json;
-    let db = depot.obtain().unwrap();
+    let repo = depot.obtain::<DynRepo>().unwrap();
 
-    if verify_password(db, json.password).await? {
+    let verified = repo.verify_password(json.password).await?;
+    depot.obtain::<Arc<Metrics>>().unwrap().observe_login(verified);
+    if verified {
+        let epoch = depot.obtain::<SessionEpoch>().unwrap().get();
         let mut session = Session::new();
         session.insert("logged", true)?;
+        session.insert("epoch", epoch)?;
         depot.set_session(session);
         Ok(Response::empty())
     } else {
@@ -43,7 +54,8 @@ async fn login(json: Json<LoginPost>, depot: &mut Depot) -> RespResult<()> {
     }
 }
 
-#[handler]
+/// 检查当前会话是否已经登录
+#[endpoint(tags("auth"), security(["session" = []]))]
 async fn is_logged(depot: &mut Depot) -> RespResult<()> {
     if logged(depot) {
         Ok(Response::empty())
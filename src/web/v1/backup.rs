@@ -0,0 +1,47 @@
+use salvo::{Depot, Router, oapi::endpoint};
+
+use crate::{
+    db::{
+        backup::{BackupInfo, Backups},
+        repo::{DynRepo, require_surreal},
+    },
+    storage::DynStorage,
+    web::{
+        extractors::logged,
+        resp::{RespResult, Response},
+    },
+};
+
+pub fn router() -> Router {
+    Router::with_path("backup").get(list).post(create)
+}
+
+/// 列出已有的备份
+#[endpoint(tags("backup"), security(["session" = []]))]
+async fn list(depot: &mut Depot) -> RespResult<Vec<BackupInfo>> {
+    if !logged(depot) {
+        return Err(Response::custom(403, "not logged"));
+    }
+    let backups = depot.obtain::<Backups>().unwrap();
+    backups.list().await.map(Response::ok).map_err(Into::into)
+}
+
+/// 立即创建一份备份, 已经有备份在进行时等待它完成
+///
+/// 使用SQLite保存数据时不支持, 返回501
+#[endpoint(tags("backup"), security(["session" = []]))]
+async fn create(depot: &mut Depot) -> RespResult<BackupInfo> {
+    if !logged(depot) {
+        return Err(Response::custom(403, "not logged"));
+    }
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let db =
+        require_surreal(repo.as_ref()).map_err(|err| Response::custom(501, err.to_string()))?;
+    let storage = depot.obtain::<DynStorage>().unwrap();
+    let backups = depot.obtain::<Backups>().unwrap();
+    backups
+        .create(db, storage)
+        .await
+        .map(Response::ok)
+        .map_err(Into::into)
+}
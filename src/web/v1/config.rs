@@ -1,20 +1,90 @@
-use salvo::{Depot, Router, handler};
-use surrealdb::{Surreal, engine::any::Any};
+use salvo::{
+    Depot, Router, Writer,
+    oapi::{ToSchema, endpoint},
+    session::SessionDepotExt,
+};
+use serde::Deserialize;
+use std::sync::Arc;
 
 use crate::{
-    db::model::config::{ConfigRecord, query_config},
-    web::resp::{RespResult, Response},
+    db::{
+        model::config::{ConfigRecord, ConfigRecordOption, validate_config},
+        repo::DynRepo,
+    },
+    metrics::Metrics,
+    web::{
+        SessionEpoch,
+        extractors::{Json, logged},
+        resp::{RespResult, Response},
+    },
 };
 
 pub fn router() -> Router {
-    Router::with_path("config").get(get_config)
+    Router::with_path("config")
+        .get(get_config)
+        .put(update)
+        .patch(update)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[salvo(schema(name = "ConfigUpdate"))]
+pub struct ConfigUpdate {
+    #[serde(flatten)]
+    pub config: ConfigRecordOption,
+    /// 修改密码时必须提供当前密码
+    pub current_password: Option<String>,
 }
 
-#[handler]
+/// 读取站点设置
+#[endpoint(tags("config"))]
 async fn get_config(depot: &mut Depot) -> RespResult<ConfigRecord> {
-    let db = depot.obtain::<Surreal<Any>>().unwrap();
-    query_config(db)
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    repo.query_config()
+        .await
+        .map(Response::ok)
+        .map_err(Into::into)
+}
+
+/// 修改站点设置, 只更新请求中包含的字段
+///
+/// 修改密码后其他会话全部失效
+#[endpoint(tags("config"), security(["session" = []]))]
+async fn update(json: Json<ConfigUpdate>, depot: &mut Depot) -> RespResult<ConfigRecord> {
+    if !logged(depot) {
+        return Err(Response::custom(403, "not logged"));
+    }
+
+    let Json(ConfigUpdate {
+        config,
+        current_password,
+    }) = json;
+    validate_config(&config).map_err(|msg| Response::custom(400, msg))?;
+
+    let repo = depot.obtain::<DynRepo>().unwrap().clone();
+    let password_changed = config.password.is_some();
+    if password_changed {
+        let Some(current) = current_password else {
+            return Err(Response::custom(400, "current password is required"));
+        };
+        if !repo.verify_password(current).await? {
+            return Err(Response::custom(401, "current password is incorrect"));
+        }
+    }
+
+    repo.update_config(config).await?;
+
+    if password_changed {
+        // 递增会话纪元使其他会话失效, 当前会话换成新的纪元继续保持登录
+        let epoch = repo.bump_session_epoch().await?;
+        depot.obtain::<SessionEpoch>().unwrap().set(epoch);
+        depot.obtain::<Arc<Metrics>>().unwrap().revoke_sessions();
+        if let Some(session) = depot.session_mut() {
+            session.insert("epoch", epoch)?;
+        }
+    }
+
+    repo.query_config()
         .await
-        .map(|c| Response::ok(c))
+        .map(Response::ok)
         .map_err(Into::into)
 }
@@ -0,0 +1,37 @@
+use salvo::{
+    Depot, Router,
+    oapi::{ToSchema, endpoint},
+};
+use serde::Serialize;
+
+use crate::{
+    db::repo::{CacheStats, DynRepo},
+    web::resp::{RespResult, Response},
+};
+
+/// 不经过安装检查, 安装前也可以用来检查服务状态
+pub fn router() -> Router {
+    Router::with_path("v1/health").get(health)
+}
+
+#[derive(Serialize, ToSchema)]
+#[salvo(schema(name = "Health"))]
+pub struct Health {
+    pub database: bool,
+    /// 关闭缓存时为空
+    pub cache: Option<CacheStats>,
+}
+
+/// 数据库和缓存的状态
+#[endpoint(tags("health"))]
+async fn health(depot: &mut Depot) -> RespResult<Health> {
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    if repo.health().await {
+        Ok(Response::ok(Health {
+            database: true,
+            cache: repo.cache().map(|cache| cache.stats()),
+        }))
+    } else {
+        Err(Response::custom(503, "database unreachable"))
+    }
+}
@@ -1,7 +1,9 @@
-use salvo::{Depot, Router, Writer, handler};
-use surrealdb::{Surreal, engine::any::Any};
+use salvo::{Depot, Router, Writer, oapi::endpoint};
 
-use crate::db::model::config::{create_config, ConfigRecord};
+use crate::db::{
+    model::config::{ConfigRecord, RepeatInstallation},
+    repo::DynRepo,
+};
 use crate::web::Installed;
 use crate::web::extractors::Json;
 use crate::web::resp::{RespResult, Response};
@@ -10,14 +12,18 @@ pub fn router() -> Router {
     Router::with_path("install").post(install)
 }
 
-#[handler]
+/// 初始化站点设置, 安装前其他接口都返回`code`为0的`uninitialized`
+#[endpoint(tags("install"))]
 async fn install(config: Json<ConfigRecord>, depot: &mut Depot) -> RespResult<()> {
     let Json(config) = config;
-    let db = depot.obtain::<Surreal<Any>>().unwrap();
-    if depot.contains::<Installed>() {
-        return Err(Response::error("repeat installation"));
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let installed = depot.obtain::<Installed>().unwrap();
+    if installed.get() {
+        return Err(RepeatInstallation.into());
     }
 
-    create_config(db, config).await?;
+    // 并发的安装请求可能同时通过上面的检查, 由数据库事务保证只有一个能成功
+    repo.create_config(config).await?;
+    installed.set();
     Ok(Response::empty())
 }
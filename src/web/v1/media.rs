@@ -0,0 +1,281 @@
+use salvo::{
+    Depot, Request, Router,
+    http::{
+        HeaderValue, StatusCode,
+        header::{
+            CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE, ETAG, X_CONTENT_TYPE_OPTIONS,
+        },
+        mime,
+    },
+    oapi::{
+        Array, BasicType, Components, KnownFormat, Object, RefOr, Schema, SchemaFormat, ToSchema,
+        endpoint,
+    },
+    size_limiter::max_size,
+};
+use serde::Serialize;
+use smol_str::{SmolStr, ToSmolStr};
+
+use crate::{
+    db::{
+        model::media::{MediaRecord, file_url, store_media},
+        repo::DynRepo,
+    },
+    storage::DynStorage,
+    thumbnail::{VARIANT_WIDTHS, is_resizable, resize_to_webp, variant_key, variant_widths},
+    web::{
+        conditional::if_none_match,
+        extractors::logged,
+        resp::{RespResult, Response},
+    },
+};
+
+/// 单个文件的大小上限
+const MAX_UPLOAD_SIZE: u64 = 32 * 1024 * 1024;
+/// 整个上传请求的大小上限, 在salvo把文件写入临时目录之前按`Content-Length`检查
+const MAX_REQUEST_SIZE: u64 = 4 * MAX_UPLOAD_SIZE;
+
+pub fn router() -> Router {
+    Router::with_path("media")
+        .get(list)
+        .push(Router::new().hoop(max_size(MAX_REQUEST_SIZE)).post(upload))
+        .push(Router::with_path("<id>").get(get_media).delete(delete))
+        .push(Router::with_path("<id>/file").get(serve))
+}
+
+#[derive(Serialize, ToSchema)]
+#[salvo(schema(name = "MediaView"))]
+pub struct MediaView {
+    #[serde(flatten)]
+    pub media: MediaRecord,
+    pub url: String,
+    /// 可以直接用于`<img srcset>`的候选列表, 不是图片时为空
+    pub srcset: Vec<MediaVariant>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[salvo(schema(name = "MediaVariant"))]
+pub struct MediaVariant {
+    pub width: u32,
+    pub url: String,
+}
+
+impl From<MediaRecord> for MediaView {
+    fn from(media: MediaRecord) -> Self {
+        let url = file_url(&media.id);
+        let mut srcset = Vec::new();
+        if let Some(width) = media.width.filter(|_| is_resizable(&media.mime)) {
+            srcset.extend(variant_widths(width).map(|w| MediaVariant {
+                width: w,
+                url: format!("{url}?w={w}"),
+            }));
+            srcset.push(MediaVariant {
+                width,
+                url: url.clone(),
+            });
+        }
+        MediaView { media, url, srcset }
+    }
+}
+
+/// 文档中的文件内容
+struct Binary;
+
+impl ToSchema for Binary {
+    fn to_schema(_components: &mut Components) -> RefOr<Schema> {
+        Object::with_type(BasicType::String)
+            .format(SchemaFormat::KnownFormat(KnownFormat::Binary))
+            .into()
+    }
+}
+
+/// 上传接口的multipart表单, 只用于生成文档
+struct UploadForm;
+
+impl ToSchema for UploadForm {
+    fn to_schema(components: &mut Components) -> RefOr<Schema> {
+        Object::new()
+            .property("file", Array::new().items(Binary::to_schema(components)))
+            .required("file")
+            .into()
+    }
+}
+
+/// 上传文件, 一次可以包含多个`file`字段
+#[endpoint(
+    tags("media"),
+    security(["session" = []]),
+    request_body(content = inline(UploadForm), content_type = "multipart/form-data")
+)]
+async fn upload(req: &mut Request, depot: &mut Depot) -> RespResult<Vec<MediaView>> {
+    if !logged(depot) {
+        return Err(Response::custom(403, "not logged"));
+    }
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let storage = depot.obtain::<DynStorage>().unwrap();
+
+    let Some(files) = req.files("file").await else {
+        return Err(Response::custom(400, "no file uploaded"));
+    };
+    let mut uploaded = Vec::with_capacity(files.len());
+    for file in files {
+        if file.size() > MAX_UPLOAD_SIZE {
+            return Err(Response::custom(413, "file is too large"));
+        }
+        let data = tokio::fs::read(file.path()).await?;
+        let filename = file.name().unwrap_or("unnamed").to_smolstr();
+        let mime = file
+            .content_type()
+            .filter(|mime| *mime != mime::APPLICATION_OCTET_STREAM)
+            .unwrap_or_else(|| mime_guess::from_path(&*filename).first_or_octet_stream());
+        let (media, _) = store_media(repo.as_ref(), storage, filename, mime, data).await?;
+        uploaded.push(media.into());
+    }
+    Ok(Response::ok(uploaded))
+}
+
+/// 按上传时间分页列出文件
+#[endpoint(
+    tags("media"),
+    security(["session" = []]),
+    parameters(
+        ("page" = Option<usize>, Query, description = "从0开始的页码"),
+        ("size" = Option<usize>, Query, description = "每页的数量, 默认20, 最多100"),
+    )
+)]
+async fn list(req: &mut Request, depot: &mut Depot) -> RespResult<Vec<MediaView>> {
+    if !logged(depot) {
+        return Err(Response::custom(403, "not logged"));
+    }
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let page = req.query::<usize>("page").unwrap_or_default();
+    let size = req.query::<usize>("size").unwrap_or(20).clamp(1, 100);
+    repo.query_media_by_page(page, size)
+        .await
+        .map(|media| Response::ok(media.into_iter().map(Into::into).collect()))
+        .map_err(Into::into)
+}
+
+/// 读取文件的信息
+#[endpoint(tags("media"), parameters(("id" = String, Path, description = "文件id")))]
+async fn get_media(req: &mut Request, depot: &mut Depot) -> RespResult<MediaView> {
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let id = req.param::<SmolStr>("id").unwrap_or_default();
+    match repo.query_media(id).await? {
+        Some(media) => Ok(Response::ok(media.into())),
+        None => Err(Response::custom(404, "media not found")),
+    }
+}
+
+/// 删除文件和它的缩略图
+#[endpoint(
+    tags("media"),
+    security(["session" = []]),
+    parameters(("id" = String, Path, description = "文件id"))
+)]
+async fn delete(req: &mut Request, depot: &mut Depot) -> RespResult<()> {
+    if !logged(depot) {
+        return Err(Response::custom(403, "not logged"));
+    }
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let storage = depot.obtain::<DynStorage>().unwrap();
+    let id = req.param::<SmolStr>("id").unwrap_or_default();
+
+    let Some(media) = repo.delete_media(id).await? else {
+        return Err(Response::custom(404, "media not found"));
+    };
+    // 内容相同的文件只有一条记录, 删除记录后文件不再被引用
+    storage.delete(&media.hash).await?;
+    for width in VARIANT_WIDTHS {
+        storage.delete(&variant_key(&media.hash, width)).await?;
+    }
+    Ok(Response::empty())
+}
+
+/// 读取文件内容, 失败时返回json的`Response`
+#[endpoint(
+    tags("media"),
+    parameters(
+        ("id" = String, Path, description = "文件id"),
+        ("w" = Option<u32>, Query, description = "缩略图的宽度, 只接受`srcset`中列出的宽度"),
+    ),
+    responses(
+        (
+            status_code = 200,
+            description = "文件内容, `Content-Type`为上传时的类型",
+            body = inline(Binary),
+            content_type = "application/octet-stream"
+        ),
+        (status_code = 304, description = "和`If-None-Match`中的ETag一致"),
+    )
+)]
+async fn serve(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut salvo::Response,
+) -> Result<(), Response<()>> {
+    let repo = depot.obtain::<DynRepo>().unwrap();
+    let storage = depot.obtain::<DynStorage>().unwrap();
+    let id = req.param::<SmolStr>("id").unwrap_or_default();
+
+    let Some(media) = repo.query_media(id).await? else {
+        return Err(Response::custom(404, "media not found"));
+    };
+    // 请求缩略图时只接受`srcset`中列出的宽度, 避免生成任意尺寸的文件
+    let width = match req.query::<u32>("w") {
+        Some(w) if media.width.is_some_and(|width| w == width) => None,
+        Some(w)
+            if is_resizable(&media.mime)
+                && media
+                    .width
+                    .is_some_and(|width| variant_widths(width).any(|v| v == w)) =>
+        {
+            Some(w)
+        }
+        Some(_) => return Err(Response::custom(400, "unsupported width")),
+        None => None,
+    };
+
+    // 文件内容由哈希决定, 不会改变, 可以让客户端永久缓存
+    let etag = match width {
+        Some(w) => format!("\"{}-w{w}\"", media.hash),
+        None => format!("\"{}\"", media.hash),
+    };
+    res.add_header(ETAG, &etag, true)?;
+    res.add_header(CACHE_CONTROL, "public, max-age=31536000, immutable", true)?;
+    if if_none_match(req, &etag) {
+        res.status_code(StatusCode::NOT_MODIFIED);
+        return Ok(());
+    }
+
+    let (data, mime) = match width {
+        Some(w) => (variant(storage, &media.hash, w).await?, "image/webp"),
+        None => (original(storage, &media.hash).await?, &*media.mime),
+    };
+    res.add_header(CONTENT_TYPE, mime, true)?;
+    res.headers_mut()
+        .insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    // 防止上传的html/svg在本站的源下执行脚本
+    res.headers_mut()
+        .insert(CONTENT_SECURITY_POLICY, HeaderValue::from_static("sandbox"));
+    res.write_body(data)?;
+    Ok(())
+}
+
+async fn original(storage: &DynStorage, hash: &str) -> Result<Vec<u8>, Response<()>> {
+    storage
+        .get(hash)
+        .await?
+        .ok_or_else(|| Response::custom(404, "media file is missing"))
+}
+
+/// 读取缓存的缩略图, 不存在时才读取原图生成并写入存储后端
+async fn variant(storage: &DynStorage, hash: &str, width: u32) -> Result<Vec<u8>, Response<()>> {
+    let key = variant_key(hash, width);
+    if let Some(data) = storage.get(&key).await? {
+        return Ok(data);
+    }
+    let data = resize_to_webp(original(storage, hash).await?, width).await?;
+    storage.put(&key, data.clone()).await?;
+    Ok(data)
+}
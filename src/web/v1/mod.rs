@@ -3,14 +3,19 @@ use salvo::{Router, handler};
 use super::resp::Response;
 
 mod auth;
+mod backup;
 mod config;
+pub(super) mod health;
 mod install;
+mod media;
 
 pub fn router() -> Router {
     Router::with_path("v1")
         .push(install::router())
         .push(config::router())
         .push(auth::router())
+        .push(media::router())
+        .push(backup::router())
 }
 
 #[handler]